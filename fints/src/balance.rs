@@ -0,0 +1,121 @@
+use crate::data_types::*;
+use crate::de::{self, RawSegment};
+use crate::segments::*;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::de::Error as _;
+use serde_derive::{Deserialize, Serialize};
+
+/// Balance of an account. Debit amounts are negative.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Balance {
+    /// Balance of all booked transactions.
+    pub booked: Decimal,
+
+    /// Balance including pending transactions, if the bank reports it.
+    pub pending: Option<Decimal>,
+
+    pub credit_line: Option<Decimal>,
+
+    /// Amount available for withdrawals and transfers.
+    pub available: Option<Decimal>,
+
+    pub currency: String,
+
+    /// Date the booked balance refers to.
+    pub booking_date: NaiveDate,
+}
+
+impl Balance {
+    /// Read the balance from a HISAL segment in any supported version.
+    pub fn from_segment(segment: &RawSegment) -> Result<Balance, de::Error> {
+        match segment.version() {
+            Some(Seg_HISAL_Balance6::VERSION) => {
+                let balance: Seg_HISAL_Balance6 = segment.deserialize()?;
+                Ok(Balance::new(
+                    balance.currency,
+                    balance.booked,
+                    balance.pending,
+                    balance.credit_line,
+                    balance.available,
+                    balance.booking_timestamp,
+                ))
+            }
+            Some(Seg_HISAL_Balance7::VERSION) => {
+                let balance: Seg_HISAL_Balance7 = segment.deserialize()?;
+                Ok(Balance::new(
+                    balance.currency,
+                    balance.booked,
+                    balance.pending,
+                    balance.credit_line,
+                    balance.available,
+                    balance.booking_timestamp,
+                ))
+            }
+            version => Err(de::Error::custom(format!(
+                "Unsupported HISAL version {:?}",
+                version
+            ))),
+        }
+    }
+
+    fn new(
+        currency: String,
+        booked: DEG_Balance,
+        pending: Option<DEG_Balance>,
+        credit_line: Option<DEG_Amount>,
+        available: Option<DEG_Amount>,
+        booking_timestamp: Option<DEG_Timestamp>,
+    ) -> Balance {
+        Balance {
+            booked: booked.signed_value(),
+            pending: pending.map(|balance| balance.signed_value()),
+            credit_line: credit_line.map(|amount| amount.value),
+            available: available.map(|amount| amount.value),
+            currency,
+            booking_date: booking_timestamp.map_or(booked.date, |timestamp| timestamp.date),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::de::RawMessage;
+    use pretty_assertions::assert_eq;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_balance() {
+        let response = RawMessage::from_bytes(
+            b"HISAL:4:7:3+DE00123456780001234567:TESTDEFFXXX:1234567::280:12345678\
+              +Girokonto+EUR+C:1234,56:EUR:20201231+D:10,5:EUR:20201231:120000+2000,:EUR\
+              +3224,56:EUR'\
+              HISAL:5:6:3+1234567::280:12345678+Girokonto+EUR+D:99,:EUR:20201230\
+              ++++++20201231:080000'",
+        )
+        .unwrap();
+
+        let balance = Balance::from_segment(&response.segments[0]).unwrap();
+        assert_eq!(
+            balance,
+            Balance {
+                booked: Decimal::from_str("1234.56").unwrap(),
+                pending: Some(Decimal::from_str("-10.5").unwrap()),
+                credit_line: Some(Decimal::from(2000)),
+                available: Some(Decimal::from_str("3224.56").unwrap()),
+                currency: "EUR".to_string(),
+                booking_date: NaiveDate::from_ymd_opt(2020, 12, 31).unwrap(),
+            }
+        );
+
+        let balance = Balance::from_segment(&response.segments[1]).unwrap();
+        assert_eq!(balance.booked, Decimal::from(-99));
+        assert_eq!(balance.pending, None);
+        assert_eq!(balance.available, None);
+        assert_eq!(
+            balance.booking_date,
+            NaiveDate::from_ymd_opt(2020, 12, 31).unwrap()
+        );
+    }
+}
@@ -1,16 +1,35 @@
-use fints::PinTanClient;
-use pretty_env_logger;
+use fints::{ClientState, HttpTransport, PinTanClient};
+use std::fs;
+
+/// Customer system id, BPD and UPD are kept here between runs.
+const STATE_FILE: &str = "fints-state.json";
 
 pub fn main() {
     pretty_env_logger::init();
 
-    let client = PinTanClient {
+    let state: ClientState = fs::read_to_string(STATE_FILE)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+    let mut client = PinTanClient {
         url: "http://127.0.0.1:3000/cgi-bin/hbciservlet".to_string(),
         bank_code: 12345678,
         username: "test1".to_string(),
         pin: "1234".to_string(),
+        state,
+        transport: Box::new(HttpTransport),
     };
-    let accounts = client.get_accounts();
     println!("{:#?}", client);
-    println!("{:#?}", accounts);
+    match client.get_accounts() {
+        Ok(accounts) => println!("{:#?}", accounts),
+        Err(err) => eprintln!("Error: {}", err),
+    }
+    match serde_json::to_string_pretty(&client.state) {
+        Ok(json) => {
+            if let Err(err) = fs::write(STATE_FILE, json) {
+                eprintln!("Failed to save state: {}", err);
+            }
+        }
+        Err(err) => eprintln!("Failed to save state: {}", err),
+    }
 }
@@ -0,0 +1,378 @@
+//! Parser for camt.052 account reports as returned by HKCAZ.
+//!
+//! Elements are matched by their local name, so all camt.052 versions are supported as long as
+//! they keep the element names.
+
+use crate::de;
+use crate::transaction::{Transaction, TransactionStatus};
+use chrono::NaiveDate;
+use roxmltree::{Document, Node};
+use rust_decimal::Decimal;
+use serde::de::Error as _;
+
+type Result<T> = std::result::Result<T, de::Error>;
+
+/// Prefix of the descriptors of all camt.052 versions.
+pub const CAMT_052_DESCRIPTOR: &str = "urn:iso:std:iso:20022:tech:xsd:camt.052";
+
+/// Parse the entries of a camt.052 document. `status` is used for entries without status.
+///
+/// A batch booking is a single entry with the details of each of its transactions, these are
+/// returned as separate transactions.
+pub fn parse(document: &[u8], status: TransactionStatus) -> Result<Vec<Transaction>> {
+    let text = std::str::from_utf8(document).map_err(de::Error::custom)?;
+    let document = Document::parse(text).map_err(de::Error::custom)?;
+    let mut transactions = vec![];
+    for entry in document
+        .descendants()
+        .filter(|node| is_element(*node, "Ntry"))
+    {
+        transactions.extend(parse_entry(entry, status)?);
+    }
+    Ok(transactions)
+}
+
+fn is_element(node: Node, name: &str) -> bool {
+    node.is_element() && node.tag_name().name() == name
+}
+
+/// The descendant at `path`, following the first matching child in each step.
+fn find<'a, 'input>(node: Node<'a, 'input>, path: &[&str]) -> Option<Node<'a, 'input>> {
+    path.iter().try_fold(node, |node, name| {
+        node.children().find(|child| is_element(*child, name))
+    })
+}
+
+fn text(node: Node, path: &[&str]) -> Option<String> {
+    find(node, path)?
+        .text()
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(str::to_string)
+}
+
+/// A date given as `Dt` or `DtTm` below `path`.
+fn date(node: Node, path: &[&str]) -> Result<Option<NaiveDate>> {
+    let node = match find(node, path) {
+        Some(node) => node,
+        None => return Ok(None),
+    };
+    let value = match text(node, &["Dt"]).or_else(|| text(node, &["DtTm"])) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let date = value.get(..10).unwrap_or(&value);
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(Some)
+        .map_err(|_| de::Error::custom(format!("Invalid camt date {:?}", value)))
+}
+
+/// An amount in `node`, negative if `is_debit`, and its currency.
+fn amount(node: Node, is_debit: bool) -> Result<(Decimal, String)> {
+    let amount: Decimal = node
+        .text()
+        .unwrap_or_default()
+        .trim()
+        .parse()
+        .map_err(|_| de::Error::custom("Invalid camt amount"))?;
+    let currency = node.attribute("Ccy").unwrap_or_default().to_string();
+    Ok((if is_debit { -amount } else { amount }, currency))
+}
+
+fn parse_entry(entry: Node, default_status: TransactionStatus) -> Result<Vec<Transaction>> {
+    let amount_node =
+        find(entry, &["Amt"]).ok_or_else(|| de::Error::custom("camt entry without amount"))?;
+    let is_debit = text(entry, &["CdtDbtInd"]).as_deref() == Some("DBIT");
+    let (amount, currency) = amount(amount_node, is_debit)?;
+
+    // `Sts` contains the code directly up to camt.052.001.07 and in `Cd` afterwards.
+    let status = match text(entry, &["Sts"]).or_else(|| text(entry, &["Sts", "Cd"])) {
+        Some(status) if status == "BOOK" => TransactionStatus::Booked,
+        Some(status) if status == "PDNG" => TransactionStatus::Pending,
+        _ => default_status,
+    };
+
+    // German banks put the business transaction code and primanota into the proprietary
+    // code, e.g. `NTRF+166+9310`.
+    let proprietary_code = text(entry, &["BkTxCd", "Prtry", "Cd"]).unwrap_or_default();
+    let mut proprietary_code = proprietary_code.split('+').skip(1);
+
+    let transaction = Transaction {
+        status,
+        booking_date: date(entry, &["BookgDt"])?,
+        value_date: date(entry, &["ValDt"])?,
+        amount,
+        currency,
+        transaction_code: proprietary_code.next().map(str::to_string),
+        primanota: proprietary_code.next().map(str::to_string),
+        booking_text: text(entry, &["AddtlNtryInf"]),
+        bank_reference: text(entry, &["AcctSvcrRef"]),
+        ..Transaction::default()
+    };
+
+    let details: Vec<_> = entry
+        .children()
+        .filter(|node| is_element(*node, "NtryDtls"))
+        .flat_map(|entry_details| entry_details.children())
+        .filter(|node| is_element(*node, "TxDtls"))
+        .collect();
+    if details.is_empty() {
+        return Ok(vec![transaction]);
+    }
+    details
+        .into_iter()
+        .map(|details| {
+            let mut transaction = transaction.clone();
+            parse_transaction_details(details, is_debit, &mut transaction)?;
+            Ok(transaction)
+        })
+        .collect()
+}
+
+fn parse_transaction_details(
+    details: Node,
+    is_debit: bool,
+    transaction: &mut Transaction,
+) -> Result<()> {
+    // Transactions of a batch booking have their own amount, in `AmtDtls` up to
+    // camt.052.001.07 and directly in `Amt` afterwards.
+    let is_debit = match text(details, &["CdtDbtInd"]) {
+        Some(indicator) => indicator == "DBIT",
+        None => is_debit,
+    };
+    if let Some(amount_node) =
+        find(details, &["AmtDtls", "TxAmt", "Amt"]).or_else(|| find(details, &["Amt"]))
+    {
+        let (amount, currency) = amount(amount_node, is_debit)?;
+        transaction.amount = amount;
+        transaction.currency = currency;
+    }
+
+    transaction.end_to_end_reference =
+        text(details, &["Refs", "EndToEndId"]).filter(|reference| reference != "NOTPROVIDED");
+    transaction.customer_reference = transaction.end_to_end_reference.clone();
+    transaction.mandate_reference = text(details, &["Refs", "MndtId"]);
+
+    // The counterparty of a debit is the creditor and vice versa.
+    let (party, account, agent) = if is_debit {
+        ("Cdtr", "CdtrAcct", "CdtrAgt")
+    } else {
+        ("Dbtr", "DbtrAcct", "DbtrAgt")
+    };
+    // The party is nested in `Pty` since camt.052.001.08.
+    transaction.counterparty_name = text(details, &["RltdPties", party, "Nm"])
+        .or_else(|| text(details, &["RltdPties", party, "Pty", "Nm"]));
+    transaction.counterparty_iban = text(details, &["RltdPties", account, "Id", "IBAN"]);
+    transaction.counterparty_bic = text(details, &["RltdAgts", agent, "FinInstnId", "BIC"])
+        .or_else(|| text(details, &["RltdAgts", agent, "FinInstnId", "BICFI"]));
+    transaction.creditor_id = text(
+        details,
+        &["RltdPties", "Cdtr", "Id", "PrvtId", "Othr", "Id"],
+    )
+    .or_else(|| {
+        text(
+            details,
+            &["RltdPties", "Cdtr", "Pty", "Id", "PrvtId", "Othr", "Id"],
+        )
+    });
+
+    let purpose: Vec<_> = find(details, &["RmtInf"])
+        .into_iter()
+        .flat_map(|remittance| remittance.children())
+        .filter(|node| is_element(*node, "Ustrd"))
+        .filter_map(|node| node.text())
+        .collect();
+    if !purpose.is_empty() {
+        transaction.purpose = Some(purpose.concat());
+    }
+    if transaction.booking_text.is_none() {
+        transaction.booking_text = text(details, &["AddtlTxInf"]);
+    }
+    Ok(())
+}
+
+/// The newest camt.052 version among the descriptors a bank announced in HICAZS.
+pub fn select_descriptor<'a, I>(descriptors: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    descriptors
+        .into_iter()
+        .map(String::as_str)
+        .filter(|descriptor| descriptor.starts_with(CAMT_052_DESCRIPTOR))
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::str::FromStr;
+
+    const DOCUMENT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.052.001.02">
+  <BkToCstmrAcctRpt>
+    <Rpt>
+      <Acct><Id><IBAN>DE00123456780001234567</IBAN></Id></Acct>
+      <Ntry>
+        <Amt Ccy="EUR">10.50</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <BookgDt><Dt>2021-01-02</Dt></BookgDt>
+        <ValDt><Dt>2020-12-31</Dt></ValDt>
+        <AcctSvcrRef>BANK1</AcctSvcrRef>
+        <BkTxCd><Prtry><Cd>NDDT+106+9310</Cd><Issr>DK</Issr></Prtry></BkTxCd>
+        <NtryDtls>
+          <TxDtls>
+            <Refs><EndToEndId>E2E-1</EndToEndId><MndtId>M-1</MndtId></Refs>
+            <RltdPties>
+              <Cdtr>
+                <Nm>Stadtwerke Musterstadt</Nm>
+                <Id><PrvtId><Othr><Id>DE98ZZZ09999999999</Id></Othr></PrvtId></Id>
+              </Cdtr>
+              <CdtrAcct><Id><IBAN>DE00123456780007654321</IBAN></Id></CdtrAcct>
+            </RltdPties>
+            <RltdAgts><CdtrAgt><FinInstnId><BIC>TESTDEFFXXX</BIC></FinInstnId></CdtrAgt></RltdAgts>
+            <RmtInf><Ustrd>Strom Dezember </Ustrd><Ustrd>2020</Ustrd></RmtInf>
+          </TxDtls>
+        </NtryDtls>
+        <AddtlNtryInf>SEPA-LASTSCHRIFT</AddtlNtryInf>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">30.00</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <BookgDt><Dt>2021-01-03</Dt></BookgDt>
+        <ValDt><Dt>2021-01-03</Dt></ValDt>
+        <AcctSvcrRef>BANK2</AcctSvcrRef>
+        <NtryDtls>
+          <Btch><NbOfTxs>2</NbOfTxs></Btch>
+          <TxDtls>
+            <Refs><EndToEndId>E2E-2</EndToEndId></Refs>
+            <AmtDtls><TxAmt><Amt Ccy="EUR">10.00</Amt></TxAmt></AmtDtls>
+            <RltdPties><Cdtr><Nm>Erika Mustermann</Nm></Cdtr></RltdPties>
+          </TxDtls>
+          <TxDtls>
+            <Refs><EndToEndId>E2E-3</EndToEndId></Refs>
+            <AmtDtls><TxAmt><Amt Ccy="EUR">20.00</Amt></TxAmt></AmtDtls>
+            <RltdPties><Cdtr><Nm>Max Mustermann</Nm></Cdtr></RltdPties>
+          </TxDtls>
+        </NtryDtls>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">5</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts><Cd>PDNG</Cd></Sts>
+        <ValDt><DtTm>2021-01-04T10:00:00</DtTm></ValDt>
+        <NtryDtls>
+          <TxDtls>
+            <Refs><EndToEndId>NOTPROVIDED</EndToEndId></Refs>
+            <RltdPties><Dbtr><Pty><Nm>Max Mustermann</Nm></Pty></Dbtr></RltdPties>
+          </TxDtls>
+        </NtryDtls>
+      </Ntry>
+    </Rpt>
+  </BkToCstmrAcctRpt>
+</Document>"#;
+
+    #[test]
+    fn test_parse_camt() {
+        let transactions = parse(DOCUMENT.as_bytes(), TransactionStatus::Booked).unwrap();
+        assert_eq!(transactions.len(), 4);
+        assert_eq!(
+            transactions[0],
+            Transaction {
+                status: TransactionStatus::Booked,
+                booking_date: NaiveDate::from_ymd_opt(2021, 1, 2),
+                value_date: NaiveDate::from_ymd_opt(2020, 12, 31),
+                amount: Decimal::from_str("-10.50").unwrap(),
+                currency: "EUR".to_string(),
+                transaction_code: Some("106".to_string()),
+                booking_text: Some("SEPA-LASTSCHRIFT".to_string()),
+                primanota: Some("9310".to_string()),
+                purpose: Some("Strom Dezember 2020".to_string()),
+                counterparty_name: Some("Stadtwerke Musterstadt".to_string()),
+                counterparty_iban: Some("DE00123456780007654321".to_string()),
+                counterparty_bic: Some("TESTDEFFXXX".to_string()),
+                end_to_end_reference: Some("E2E-1".to_string()),
+                mandate_reference: Some("M-1".to_string()),
+                creditor_id: Some("DE98ZZZ09999999999".to_string()),
+                customer_reference: Some("E2E-1".to_string()),
+                bank_reference: Some("BANK1".to_string()),
+            }
+        );
+
+        // A batch booking with one transaction per `TxDtls`
+        let batch: Vec<_> = transactions[1..3]
+            .iter()
+            .map(|t| {
+                (
+                    t.amount,
+                    t.customer_reference.as_deref(),
+                    t.counterparty_name.as_deref(),
+                    t.bank_reference.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            batch,
+            vec![
+                (
+                    Decimal::from(-10),
+                    Some("E2E-2"),
+                    Some("Erika Mustermann"),
+                    Some("BANK2")
+                ),
+                (
+                    Decimal::from(-20),
+                    Some("E2E-3"),
+                    Some("Max Mustermann"),
+                    Some("BANK2")
+                ),
+            ]
+        );
+
+        let transaction = &transactions[3];
+        assert_eq!(transaction.status, TransactionStatus::Pending);
+        assert_eq!(transaction.booking_date, None);
+        assert_eq!(transaction.value_date, NaiveDate::from_ymd_opt(2021, 1, 4));
+        assert_eq!(transaction.amount, Decimal::from(5));
+        assert_eq!(transaction.end_to_end_reference, None);
+        assert_eq!(
+            transaction.counterparty_name.as_deref(),
+            Some("Max Mustermann")
+        );
+    }
+
+    #[test]
+    fn test_camt_statement_segment() {
+        let segment = format!(
+            "HICAZ:4:1:3+DE00123456780001234567:TESTDEFFXXX+{}.02+@{}@{}'",
+            CAMT_052_DESCRIPTOR,
+            DOCUMENT.len(),
+            DOCUMENT
+        );
+        let response = crate::de::RawMessage::from_bytes(segment.as_bytes()).unwrap();
+        let statement: crate::segments::Seg_HICAZ_CamtStatement =
+            response.segments[0].deserialize().unwrap();
+        assert_eq!(statement.booked.statements.len(), 1);
+        assert_eq!(statement.pending, None);
+        let transactions = parse(&statement.booked.statements[0], TransactionStatus::Booked);
+        assert_eq!(transactions.unwrap().len(), 4);
+
+        let descriptors = vec![
+            "urn:iso:std:iso:20022:tech:xsd:camt.052.001.02".to_string(),
+            "urn:iso:std:iso:20022:tech:xsd:camt.052.001.08".to_string(),
+            "urn:iso:std:iso:20022:tech:xsd:camt.053.001.08".to_string(),
+        ];
+        assert_eq!(
+            select_descriptor(&descriptors),
+            Some("urn:iso:std:iso:20022:tech:xsd:camt.052.001.08")
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_camt() {
+        assert!(parse(b"<Document>", TransactionStatus::Booked).is_err());
+    }
+}
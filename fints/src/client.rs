@@ -1,11 +1,102 @@
-use encoding_rs::ISO_8859_15;
-use failure::Error;
+use chrono::NaiveDate;
+use log::{debug, warn};
+use rust_decimal::Decimal;
+use serde::de::Error as _;
 use serde_derive::{Deserialize, Serialize};
+use std::fmt;
 
-use crate::dialog::Dialog;
+use crate::balance::Balance;
+use crate::camt::{self, CAMT_052_DESCRIPTOR};
+use crate::data_types::{
+    DEG_AccountConnection, DEG_Amount, DEG_BatchTransferParameters, DEG_InstituteIdentifier,
+    DEG_InternationalAccountConnection, DEG_SepaAccountConnection, DEG_SupportedCamtMessages,
+    ReturnCode,
+};
+use crate::de::{self, RawMessage};
+use crate::dialog::{collect_feedback, touchdown, Dialog, DialogState};
+use crate::error::Error;
+use crate::messages::{decrypt, new_sepa_account_info, BusinessSegment};
+use crate::mt940;
+use crate::pain001;
+use crate::parameters::{Account, UserParameters};
+use crate::segments::*;
+use crate::state::ClientState;
+use crate::transaction::{Transaction, TransactionStatus};
+use crate::transfer::{Recipient, Transfer};
+use crate::transport::{HttpTransport, Transport};
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SepaAccount;
+/// An account addressed by its SEPA account connection (KTZ) as needed by most jobs.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SepaAccount {
+    pub iban: String,
+
+    pub bic: Option<String>,
+
+    pub account_number: String,
+
+    pub sub_account: Option<String>,
+
+    pub country_code: String,
+
+    /// Bank code or "Bankleitzahl" (blz).
+    pub bank_code: u32,
+}
+
+impl SepaAccount {
+    /// Accounts which aren't SEPA capable are skipped.
+    fn from_account_connection(account: DEG_SepaAccountConnection) -> Option<SepaAccount> {
+        if !account.is_sepa {
+            return None;
+        }
+        Some(SepaAccount {
+            iban: account.iban?,
+            bic: account.bic,
+            account_number: account.account_number,
+            sub_account: account.sub_account,
+            country_code: account.institute_identifier.country_code,
+            bank_code: account.institute_identifier.bank_code,
+        })
+    }
+
+    /// The SEPA account connection (KTZ) addressing this account in a job.
+    pub fn to_sepa_account_connection(&self) -> DEG_SepaAccountConnection {
+        DEG_SepaAccountConnection {
+            is_sepa: true,
+            iban: Some(self.iban.clone()),
+            bic: self.bic.clone(),
+            account_number: self.account_number.clone(),
+            sub_account: self.sub_account.clone(),
+            institute_identifier: self.institute_identifier(),
+        }
+    }
+
+    /// The national account connection (KTV) addressing this account in older job versions.
+    pub fn to_account_connection(&self) -> DEG_AccountConnection {
+        DEG_AccountConnection {
+            account_number: self.account_number.clone(),
+            sub_account: self.sub_account.clone(),
+            institute_identifier: self.institute_identifier(),
+        }
+    }
+
+    /// The international account connection (KTI) addressing this account in a job.
+    pub fn to_international_account_connection(&self) -> DEG_InternationalAccountConnection {
+        DEG_InternationalAccountConnection {
+            iban: Some(self.iban.clone()),
+            bic: self.bic.clone(),
+            account_number: Some(self.account_number.clone()),
+            sub_account: self.sub_account.clone(),
+            institute_identifier: Some(self.institute_identifier()),
+        }
+    }
+
+    fn institute_identifier(&self) -> DEG_InstituteIdentifier {
+        DEG_InstituteIdentifier {
+            country_code: self.country_code.clone(),
+            bank_code: self.bank_code,
+        }
+    }
+}
 
 /// The `PinTanClient` is the primary way to communicate with a bank.
 #[derive(Debug, Serialize, Deserialize)]
@@ -21,27 +112,1329 @@ pub struct PinTanClient {
 
     /// Pin or password.
     pub pin: String,
+
+    /// State kept between runs, updated by every dialog.
+    #[serde(default)]
+    pub state: ClientState,
+
+    /// Delivers the messages to the bank.
+    #[serde(skip, default = "default_transport")]
+    pub transport: Box<dyn Transport>,
+}
+
+fn default_transport() -> Box<dyn Transport> {
+    Box::new(HttpTransport)
 }
 
 impl PinTanClient {
-    pub fn get_accounts(&self) -> Vec<SepaAccount> {
+    /// The accounts the user may access as listed in the UPD.
+    pub fn get_accounts(&mut self) -> Result<Vec<Account>, Error> {
+        // The dialog initialization updates the UPD if needed.
+        self.init_dialog()?.end()?;
+        Ok(UserParameters::from_parameter_data(&self.state.upd)?.accounts)
+    }
+
+    /// The SEPA capable accounts of the user, requested in the highest version supported by
+    /// the bank.
+    pub fn get_sepa_accounts(&mut self) -> Result<Vec<SepaAccount>, Error> {
+        let mut dialog = self.init_dialog()?;
+        let version = dialog.dialog().job_version(
+            Seg_HKSPA_SepaAccountInfo1::ID,
+            &[
+                Seg_HKSPA_SepaAccountInfo1::VERSION,
+                Seg_HKSPA_SepaAccountInfo2::VERSION,
+                Seg_HKSPA_SepaAccountInfo3::VERSION,
+            ],
+        )?;
+        let response = if version == Seg_HKSPA_SepaAccountInfo3::VERSION {
+            dialog.send_job(Seg_HKSPA_SepaAccountInfo3 {
+                segment_head: Seg_HKSPA_SepaAccountInfo3::new_segment_head(0),
+                accounts: vec![],
+            })?
+        } else if version == Seg_HKSPA_SepaAccountInfo2::VERSION {
+            dialog.send_job(Seg_HKSPA_SepaAccountInfo2 {
+                segment_head: Seg_HKSPA_SepaAccountInfo2::new_segment_head(0),
+                accounts: vec![],
+            })?
+        } else {
+            dialog.send_job(new_sepa_account_info())?
+        };
+        dialog.end()?;
+
+        let mut accounts = vec![];
+        for segment in response.find_all(Seg_HISPA_SepaAccountInfo1::ID) {
+            let connections = match segment.version() {
+                Some(3) => {
+                    let sepa_account_info: Seg_HISPA_SepaAccountInfo3 = segment.deserialize()?;
+                    sepa_account_info.accounts
+                }
+                Some(2) => {
+                    let sepa_account_info: Seg_HISPA_SepaAccountInfo2 = segment.deserialize()?;
+                    sepa_account_info.accounts
+                }
+                _ => {
+                    let sepa_account_info: Seg_HISPA_SepaAccountInfo1 = segment.deserialize()?;
+                    sepa_account_info.accounts
+                }
+            };
+            accounts.extend(
+                connections
+                    .into_iter()
+                    .filter_map(SepaAccount::from_account_connection),
+            );
+        }
+        Ok(accounts)
+    }
+
+    /// Balance of an account, requested in the highest version supported by the bank. If the
+    /// bank asks for a TAN, the balance is returned once the TAN is submitted.
+    pub fn get_balance(&mut self, account: &SepaAccount) -> Result<JobStatus<'_, Balance>, Error> {
+        let dialog = self.init_dialog()?;
+        let version = dialog.dialog().job_version(
+            Seg_HKSAL_Balance6::ID,
+            &[Seg_HKSAL_Balance6::VERSION, Seg_HKSAL_Balance7::VERSION],
+        )?;
+        if version == Seg_HKSAL_Balance7::VERSION {
+            dialog.send_tan_job(
+                Seg_HKSAL_Balance7 {
+                    segment_head: Seg_HKSAL_Balance7::new_segment_head(0),
+                    account: account.to_international_account_connection(),
+                    all_accounts: false,
+                    max_entries: None,
+                    touchdown: None,
+                },
+                finish_balance,
+            )
+        } else {
+            dialog.send_tan_job(
+                Seg_HKSAL_Balance6 {
+                    segment_head: Seg_HKSAL_Balance6::new_segment_head(0),
+                    account: account.to_account_connection(),
+                    all_accounts: false,
+                    max_entries: None,
+                    touchdown: None,
+                },
+                finish_balance,
+            )
+        }
+    }
+
+    /// Booked and pending transactions of an account between two dates (inclusive) as
+    /// MT940. Further pages are requested while iterating. If the bank asks for a TAN, the
+    /// transactions are returned once the TAN is submitted.
+    pub fn get_transactions(
+        &mut self,
+        account: &SepaAccount,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<JobStatus<'_, Transactions<'_>>, Error> {
+        let dialog = self.init_dialog()?;
+        let version = dialog.dialog().job_version(
+            Seg_HKKAZ_Statement6::ID,
+            &[Seg_HKKAZ_Statement6::VERSION, Seg_HKKAZ_Statement7::VERSION],
+        )?;
+        let account = account.clone();
+        if version == Seg_HKKAZ_Statement7::VERSION {
+            let request = move |touchdown| Seg_HKKAZ_Statement7 {
+                segment_head: Seg_HKKAZ_Statement7::new_segment_head(0),
+                account: account.to_international_account_connection(),
+                all_accounts: false,
+                from_date: Some(from),
+                to_date: Some(to),
+                max_entries: None,
+                touchdown,
+            };
+            Transactions::request(dialog, request, parse_mt940_statements)
+        } else {
+            let request = move |touchdown| Seg_HKKAZ_Statement6 {
+                segment_head: Seg_HKKAZ_Statement6::new_segment_head(0),
+                account: account.to_account_connection(),
+                all_accounts: false,
+                from_date: Some(from),
+                to_date: Some(to),
+                max_entries: None,
+                touchdown,
+            };
+            Transactions::request(dialog, request, parse_mt940_statements)
+        }
+    }
+
+    /// Booked and pending transactions of an account between two dates (inclusive) as
+    /// camt.052, in the newest camt.052 version the bank announces. Further pages are requested
+    /// while iterating. If the bank asks for a TAN, the transactions are returned once the TAN
+    /// is submitted.
+    pub fn get_camt_transactions(
+        &mut self,
+        account: &SepaAccount,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<JobStatus<'_, Transactions<'_>>, Error> {
+        let dialog = self.init_dialog()?;
+        dialog.dialog().job_version(
+            Seg_HKCAZ_CamtStatement::ID,
+            &[Seg_HKCAZ_CamtStatement::VERSION],
+        )?;
+        let mut descriptors = vec![];
+        if let Some(bank_parameters) = dialog.dialog().bank_parameters() {
+            for segment in bank_parameters.job_parameters(Seg_HKCAZ_CamtStatement::ID) {
+                let parameters: Seg_HICAZS_CamtStatementParameters = segment.deserialize()?;
+                descriptors.extend(parameters.parameters.supported_camt_messages.descriptors);
+            }
+        }
+        let descriptor = camt::select_descriptor(&descriptors)
+            .ok_or_else(|| Error::UnsupportedJob(CAMT_052_DESCRIPTOR.to_string()))?
+            .to_string();
+
+        let account = account.clone();
+        let request = move |touchdown| Seg_HKCAZ_CamtStatement {
+            segment_head: Seg_HKCAZ_CamtStatement::new_segment_head(0),
+            account: account.to_international_account_connection(),
+            supported_camt_messages: DEG_SupportedCamtMessages {
+                descriptors: vec![descriptor.clone()],
+            },
+            all_accounts: false,
+            from_date: Some(from),
+            to_date: Some(to),
+            max_entries: None,
+            touchdown,
+        };
+        Transactions::request(dialog, request, parse_camt_statements)
+    }
+
+    /// Transfer `amount` EUR from `from` to `to` by SEPA credit transfer (HKCCS).
+    ///
+    /// The pain.001 document is generated in the newest version the bank accepts. If the bank
+    /// asks for a TAN, the transfer is only executed once the TAN is submitted for the returned
+    /// challenge.
+    pub fn transfer(
+        &mut self,
+        from: &SepaAccount,
+        to: &Recipient,
+        amount: Decimal,
+        purpose: &str,
+    ) -> Result<JobStatus<'_>, Error> {
+        let transfer = Transfer {
+            recipient: to.clone(),
+            amount,
+            purpose: purpose.to_string(),
+            end_to_end_reference: None,
+        };
+        let dialog = self.init_dialog()?;
+        dialog.dialog().job_version(
+            Seg_HKCCS_SepaTransfer::ID,
+            &[Seg_HKCCS_SepaTransfer::VERSION],
+        )?;
+        let descriptor = pain001_descriptor(dialog.dialog())?;
+        let document = pain001::generate(
+            &descriptor,
+            from,
+            &account_holder(dialog.dialog(), from),
+            &[transfer],
+            None,
+        )?;
+        dialog.send_tan_job(
+            Seg_HKCCS_SepaTransfer {
+                segment_head: Seg_HKCCS_SepaTransfer::new_segment_head(0),
+                account: from.to_international_account_connection(),
+                sepa_descriptor: descriptor,
+                sepa_pain_message: document,
+            },
+            |dialog, _| dialog.end(),
+        )
+    }
+
+    /// Transfer all `transfers` from `from` in one SEPA batch transfer (HKCCM) confirmed with a
+    /// single TAN.
+    ///
+    /// With `single_booking`, each transfer is booked on its own instead of as one sum. The
+    /// batch is checked against the bank's limits for the number of transfers and single
+    /// bookings before it is sent.
+    pub fn batch_transfer(
+        &mut self,
+        from: &SepaAccount,
+        transfers: &[Transfer],
+        single_booking: bool,
+    ) -> Result<JobStatus<'_>, Error> {
+        let dialog = self.init_dialog()?;
+        dialog.dialog().job_version(
+            Seg_HKCCM_SepaBatchTransfer::ID,
+            &[Seg_HKCCM_SepaBatchTransfer::VERSION],
+        )?;
+        if let Some(bank_parameters) = dialog.dialog().bank_parameters() {
+            for segment in bank_parameters.job_parameters(Seg_HKCCM_SepaBatchTransfer::ID) {
+                if segment.version() == Some(Seg_HICCMS_SepaBatchTransferParameters::VERSION) {
+                    let parameters: Seg_HICCMS_SepaBatchTransferParameters =
+                        segment.deserialize()?;
+                    check_batch_limits(&parameters.parameters, transfers.len(), single_booking)?;
+                }
+            }
+        }
+        let descriptor = pain001_descriptor(dialog.dialog())?;
+        let document = pain001::generate(
+            &descriptor,
+            from,
+            &account_holder(dialog.dialog(), from),
+            transfers,
+            Some(!single_booking),
+        )?;
+        dialog.send_tan_job(
+            Seg_HKCCM_SepaBatchTransfer {
+                segment_head: Seg_HKCCM_SepaBatchTransfer::new_segment_head(0),
+                account: from.to_international_account_connection(),
+                sum_amount: DEG_Amount {
+                    value: transfers.iter().map(|transfer| transfer.amount).sum(),
+                    currency: "EUR".to_string(),
+                },
+                single_booking: Some(single_booking),
+                sepa_descriptor: descriptor,
+                sepa_pain_message: document,
+            },
+            |dialog, _| dialog.end(),
+        )
+    }
+
+    /// Synchronize in a dialog of its own to get a new customer system id assigned by the bank.
+    pub fn sync(&mut self) -> Result<(), Error> {
+        let mut dialog = self.new_dialog();
+        let message = dialog.get_sync_message()?;
+        self.start_dialog(dialog, &message)?.end()
+    }
+
+    /// Initialize a dialog for sending jobs, synchronizing first if needed. The dialog is ended
+    /// when it is dropped.
+    pub fn init_dialog(&mut self) -> Result<OpenDialog<'_>, Error> {
+        if !self.state.is_synchronized() {
+            self.sync()?;
+        }
+        let mut dialog = self.new_dialog();
+        let message = dialog.get_init_message()?;
+        self.start_dialog(dialog, &message)
+    }
+
+    fn new_dialog(&self) -> Dialog {
         let mut dialog = Dialog::new(self.bank_code, &self.username, &self.pin);
-        self.sync(&dialog).unwrap();
-        dialog.init();
-        vec![SepaAccount]
+        dialog.client_state = self.state.clone();
+        dialog
     }
 
-    pub fn sync(&self, dialog: &Dialog) -> Result<String, Error> {
-        let client = reqwest::Client::new();
-        let msg = dialog.get_sync_message();
-        let mut response = client.post(&self.url)
-            .body(msg)
-            .send()?;
+    fn start_dialog(&mut self, dialog: Dialog, message: &[u8]) -> Result<OpenDialog<'_>, Error> {
+        // Guard the dialog right away so it's ended even if the bank answers with an error.
+        let mut dialog = OpenDialog {
+            client: self,
+            dialog,
+        };
+        dialog.client.exchange(&mut dialog.dialog, message)?;
+        Ok(dialog)
+    }
+
+    /// Send a message of the dialog and let the dialog process the response.
+    fn exchange(&mut self, dialog: &mut Dialog, message: &[u8]) -> Result<RawMessage, Error> {
+        let response = self.send(message)?;
+        dialog.process_response(&response)?;
+        Ok(response)
+    }
+
+    /// Send a message to the bank and return the response without its encryption envelope.
+    fn send(&mut self, message: &[u8]) -> Result<RawMessage, Error> {
+        let bytes = self.transport.send(&self.url, message)?;
+        let message = decrypt(RawMessage::from_bytes(&bytes)?)?;
+        debug!("Response {:#?}", message);
+        Ok(message)
+    }
+}
+
+/// A dialog that was started with the bank.
+///
+/// Some banks limit the number of concurrent dialogs, so the dialog is ended when this is
+/// dropped, e.g. because a job failed. Use [`OpenDialog::end`] to learn whether ending the
+/// dialog succeeded. Either way, the client state is updated from the dialog.
+#[derive(Debug)]
+pub struct OpenDialog<'a> {
+    client: &'a mut PinTanClient,
+    dialog: Dialog,
+}
+
+impl<'a> OpenDialog<'a> {
+    pub fn dialog(&self) -> &Dialog {
+        &self.dialog
+    }
+
+    /// Send a job and return the response. Jobs the bank wants confirmed with a TAN fail with
+    /// `Error::TanRequired`, these have to go through `send_tan_job`.
+    pub fn send_job<S>(&mut self, segment: S) -> Result<RawMessage, Error>
+    where
+        S: BusinessSegment,
+    {
+        let message = self.dialog.get_job_message(segment)?;
+        let response = self.client.exchange(&mut self.dialog, &message)?;
+        if let Some(feedback) = collect_feedback(&response)?
+            .into_iter()
+            .find(|f| f.code.requires_tan())
+        {
+            return Err(Error::TanRequired {
+                text: feedback.text,
+            });
+        }
+        Ok(response)
+    }
+
+    /// Send a job that may have to be confirmed with a TAN. If the bank asks for one, the
+    /// dialog is kept open until the TAN is submitted. `finish` takes the dialog and the
+    /// response that carries the job's results, either to this message or to the TAN.
+    pub fn send_tan_job<S, T, F>(mut self, segment: S, finish: F) -> Result<JobStatus<'a, T>, Error>
+    where
+        S: BusinessSegment + Segment,
+        F: FnOnce(OpenDialog<'a>, RawMessage) -> Result<T, Error> + 'a,
+    {
+        let (job_reference, tan_response) = match self.exchange_tan_job(segment)? {
+            TanJobResponse::Executed(response) => {
+                return Ok(JobStatus::Executed(finish(self, response)?))
+            }
+            TanJobResponse::TanRequired {
+                job_reference,
+                response,
+            } => (job_reference, response),
+        };
+        Ok(JobStatus::TanRequired(Box::new(TanChallenge {
+            dialog: self,
+            job_reference,
+            finish: Box::new(finish),
+            challenge: tan_response.challenge.unwrap_or_default(),
+            challenge_hhd_uc: tan_response.challenge_hhd_uc,
+            tan_medium_name: tan_response.tan_medium_name,
+        })))
+    }
+
+    /// Send a job, with HKTAN if the bank requires a TAN for it.
+    fn exchange_tan_job<S>(&mut self, segment: S) -> Result<TanJobResponse, Error>
+    where
+        S: BusinessSegment + Segment,
+    {
+        if self.dialog.is_tan_required(S::ID) {
+            self.dialog.job_version(
+                Seg_HKTAN_TwoStepTanSubmission::ID,
+                &[Seg_HKTAN_TwoStepTanSubmission::VERSION],
+            )?;
+        }
+        let message = self.dialog.get_tan_job_message(segment)?;
+        let response = self.client.exchange(&mut self.dialog, &message)?;
+        let feedback = collect_feedback(&response)?;
+        // The job is only carried out once it's approved in the banking app, which would need
+        // HKTAN in a version we don't support.
+        if let Some(f) = feedback
+            .iter()
+            .find(|f| f.code == ReturnCode::SecurityClearanceViaOtherChannel)
+        {
+            return Err(Error::DecoupledApprovalRequired {
+                text: f.text.clone(),
+            });
+        }
+        // Otherwise the job was executed, with 3076 the bank waived the TAN.
+        if !feedback.iter().any(|f| f.code.requires_tan()) {
+            return Ok(TanJobResponse::Executed(response));
+        }
+
+        let tan_response: Seg_HITAN_TwoStepTanResponse = response
+            .find(Seg_HITAN_TwoStepTanResponse::ID)
+            .ok_or_else(|| de::Error::custom("Response without HITAN segment"))?
+            .deserialize()?;
+        let job_reference = tan_response
+            .job_reference
+            .clone()
+            .ok_or_else(|| de::Error::custom("HITAN without job reference"))?;
+        Ok(TanJobResponse::TanRequired {
+            job_reference,
+            response: tan_response,
+        })
+    }
+
+    /// Submit the TAN for the job the bank returned `job_reference` for and return the
+    /// response carrying the job's results.
+    fn exchange_tan(&mut self, job_reference: &str, tan: &str) -> Result<RawMessage, Error> {
+        let message = self.dialog.get_tan_message(job_reference, tan)?;
+        self.client.exchange(&mut self.dialog, &message)
+    }
+
+    /// End the dialog.
+    pub fn end(mut self) -> Result<(), Error> {
+        self.end_dialog()
+    }
+
+    fn end_dialog(&mut self) -> Result<(), Error> {
+        // The bank may have ended the dialog already.
+        if self.dialog.state() != DialogState::Open {
+            return Ok(());
+        }
+        let message = self.dialog.get_end_message()?;
+        self.client.exchange(&mut self.dialog, &message)?;
+        Ok(())
+    }
+}
+
+impl Drop for OpenDialog<'_> {
+    fn drop(&mut self) {
+        if let Err(err) = self.end_dialog() {
+            warn!("Failed to end dialog {}: {}", self.dialog.dialog_id, err);
+        }
+        self.client.state = self.dialog.client_state.clone();
+    }
+}
+
+/// Response to a job sent with `OpenDialog::exchange_tan_job`.
+enum TanJobResponse {
+    /// The job was executed, the response carries its results.
+    Executed(RawMessage),
+
+    /// The bank asks for a TAN for the job.
+    TanRequired {
+        job_reference: String,
+        response: Seg_HITAN_TwoStepTanResponse,
+    },
+}
+
+/// Outcome of a job that may have to be confirmed with a TAN, with the job's results `T`.
+#[derive(Debug)]
+pub enum JobStatus<'a, T = ()> {
+    /// The bank executed the job without a TAN.
+    Executed(T),
+
+    /// The job is only executed once the TAN for the challenge is submitted.
+    TanRequired(Box<TanChallenge<'a, T>>),
+}
+
+/// Turns the response carrying a job's results into `T`, ending the dialog when done with it.
+type JobFinish<'a, T> = Box<dyn FnOnce(OpenDialog<'a>, RawMessage) -> Result<T, Error> + 'a>;
+
+/// A TAN challenge for a job. The dialog is kept open until the TAN is submitted and ended
+/// when this is dropped without submitting one.
+pub struct TanChallenge<'a, T = ()> {
+    dialog: OpenDialog<'a>,
+    job_reference: String,
+    finish: JobFinish<'a, T>,
+
+    /// Challenge text to show to the user.
+    pub challenge: String,
+
+    /// Challenge to be read by a TAN generator, e.g. an HHD flicker code or photoTAN image.
+    pub challenge_hhd_uc: Option<Vec<u8>>,
+
+    /// Name of the TAN medium to generate the TAN with.
+    pub tan_medium_name: Option<String>,
+}
+
+impl<'a, T> TanChallenge<'a, T> {
+    /// Submit the TAN, executing the job, and return its results.
+    pub fn submit(mut self, tan: &str) -> Result<T, Error> {
+        let response = self.dialog.exchange_tan(&self.job_reference, tan)?;
+        (self.finish)(self.dialog, response)
+    }
+}
+
+impl<T> fmt::Debug for TanChallenge<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TanChallenge")
+            .field("dialog", &self.dialog)
+            .field("job_reference", &self.job_reference)
+            .field("challenge", &self.challenge)
+            .field("challenge_hhd_uc", &self.challenge_hhd_uc)
+            .field("tan_medium_name", &self.tan_medium_name)
+            .finish()
+    }
+}
+
+/// Read the balance from the response to HKSAL and end the dialog.
+fn finish_balance(dialog: OpenDialog<'_>, response: RawMessage) -> Result<Balance, Error> {
+    dialog.end()?;
+    let segment = response
+        .find(Seg_HISAL_Balance6::ID)
+        .ok_or_else(|| de::Error::custom("Response without HISAL segment"))?;
+    Ok(Balance::from_segment(segment)?)
+}
+
+/// Descriptor of the newest pain.001 version the bank accepts.
+fn pain001_descriptor(dialog: &Dialog) -> Result<String, Error> {
+    let formats = match dialog.bank_parameters() {
+        Some(bank_parameters) => bank_parameters.supported_sepa_formats()?,
+        None => vec![],
+    };
+    pain001::select_descriptor(&formats)
+        .map(str::to_string)
+        .ok_or_else(|| Error::UnsupportedJob(pain001::PAIN_001_DESCRIPTOR.to_string()))
+}
+
+/// Check a batch of `count` transfers against the bank's limits for batch transfers.
+fn check_batch_limits(
+    parameters: &DEG_BatchTransferParameters,
+    count: usize,
+    single_booking: bool,
+) -> Result<(), Error> {
+    if count > parameters.max_transfer_count as usize {
+        return Err(Error::LimitExceeded(format!(
+            "{} transfers in a batch, at most {} allowed",
+            count, parameters.max_transfer_count
+        )));
+    }
+    if single_booking && !parameters.single_booking_allowed {
+        return Err(Error::LimitExceeded(
+            "Single booking of batch transfers is not allowed".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Holder of an account as listed in the UPD.
+fn account_holder(dialog: &Dialog, account: &SepaAccount) -> String {
+    dialog
+        .user_parameters()
+        .and_then(|user_parameters| {
+            user_parameters
+                .accounts
+                .iter()
+                .find(|a| a.iban.as_deref() == Some(account.iban.as_str()))
+        })
+        .map(|a| a.holder_name.clone())
+        .unwrap_or_else(|| "NOTPROVIDED".to_string())
+}
+
+/// Sends a statement job again, continuing at the given touchdown point.
+type PageRequest<'a> =
+    Box<dyn FnMut(&mut OpenDialog<'a>, String) -> Result<TanJobResponse, Error> + 'a>;
+
+/// The transactions returned by a statement job. The job is sent again with the touchdown point
+/// of each page to fetch the next one as the iterator reaches it, so 3040 never has to be
+/// handled by the caller. The dialog is ended after the last page.
+///
+/// Touchdown points are only followed for the statement jobs (HKKAZ and HKCAZ), other jobs
+/// don't page their results.
+///
+/// If the bank asks for a TAN for one of the following pages, the iterator returns
+/// `Error::TanRequired` and then `None` until the TAN for [`Transactions::tan_challenge`] is
+/// submitted with [`Transactions::submit_tan`].
+pub struct Transactions<'a> {
+    dialog: Option<OpenDialog<'a>>,
+    request: PageRequest<'a>,
+    parse: fn(&RawMessage) -> Result<Vec<Transaction>, Error>,
+    page: std::vec::IntoIter<Transaction>,
+    touchdown: Option<String>,
+
+    /// Job reference and challenge of a TAN the bank asks for before sending the next page.
+    pending_tan: Option<(String, Seg_HITAN_TwoStepTanResponse)>,
+}
+
+impl<'a> Transactions<'a> {
+    /// Send the statement job built by `request` for the first page. Each page may need a TAN.
+    fn request<S, F>(
+        dialog: OpenDialog<'a>,
+        mut request: F,
+        parse: fn(&RawMessage) -> Result<Vec<Transaction>, Error>,
+    ) -> Result<JobStatus<'a, Transactions<'a>>, Error>
+    where
+        S: BusinessSegment + Segment + 'a,
+        F: FnMut(Option<String>) -> S + 'a,
+    {
+        let segment = request(None);
+        let next_page = move |dialog: &mut OpenDialog<'a>, touchdown| {
+            dialog.exchange_tan_job(request(Some(touchdown)))
+        };
+        dialog.send_tan_job(segment, move |dialog, response| {
+            let mut transactions = Transactions {
+                dialog: Some(dialog),
+                request: Box::new(next_page),
+                parse,
+                page: Vec::new().into_iter(),
+                touchdown: None,
+                pending_tan: None,
+            };
+            transactions.read_page(&response)?;
+            Ok(transactions)
+        })
+    }
+
+    /// Challenge text of the TAN the bank asks for before sending the next page.
+    pub fn tan_challenge(&self) -> Option<&str> {
+        self.pending_tan
+            .as_ref()
+            .map(|(_, response)| response.challenge.as_deref().unwrap_or_default())
+    }
+
+    /// Challenge for a TAN generator for the TAN the bank asks for before sending the next
+    /// page, e.g. an HHD flicker code or photoTAN image.
+    pub fn tan_challenge_hhd_uc(&self) -> Option<&[u8]> {
+        self.pending_tan
+            .as_ref()
+            .and_then(|(_, response)| response.challenge_hhd_uc.as_deref())
+    }
+
+    /// Submit the TAN for [`Transactions::tan_challenge`] to continue with the next page.
+    pub fn submit_tan(&mut self, tan: &str) -> Result<(), Error> {
+        let (dialog, job_reference) = match (self.dialog.as_mut(), self.pending_tan.take()) {
+            (Some(dialog), Some((job_reference, _))) => (dialog, job_reference),
+            (Some(dialog), None) => return Err(Error::InvalidDialogState(dialog.dialog.state())),
+            (None, _) => return Err(Error::InvalidDialogState(DialogState::Ended)),
+        };
+        let result = dialog
+            .exchange_tan(&job_reference, tan)
+            .and_then(|response| self.read_page(&response));
+        if result.is_err() {
+            self.stop();
+        }
+        result
+    }
+
+    fn fetch_page(&mut self) -> Result<(), Error> {
+        let (dialog, touchdown) = match (self.dialog.as_mut(), self.touchdown.take()) {
+            (Some(dialog), Some(touchdown)) => (dialog, touchdown),
+            _ => return Ok(()),
+        };
+        match (self.request)(dialog, touchdown)? {
+            TanJobResponse::Executed(response) => self.read_page(&response),
+            TanJobResponse::TanRequired {
+                job_reference,
+                response,
+            } => {
+                let text = response.challenge.clone().unwrap_or_default();
+                self.pending_tan = Some((job_reference, response));
+                Err(Error::TanRequired { text })
+            }
+        }
+    }
+
+    fn read_page(&mut self, response: &RawMessage) -> Result<(), Error> {
+        self.touchdown = touchdown(&collect_feedback(response)?).map(str::to_string);
+        self.page = (self.parse)(response)?.into_iter();
+        if self.touchdown.is_none() {
+            if let Some(dialog) = self.dialog.take() {
+                dialog.end()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop paging, dropping the dialog ends it.
+    fn stop(&mut self) {
+        self.touchdown = None;
+        self.pending_tan = None;
+        self.dialog = None;
+    }
+}
+
+impl Iterator for Transactions<'_> {
+    type Item = Result<Transaction, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(transaction) = self.page.next() {
+                return Some(Ok(transaction));
+            }
+            if self.pending_tan.is_some() {
+                return None;
+            }
+            self.touchdown.as_ref()?;
+            if let Err(err) = self.fetch_page() {
+                if self.pending_tan.is_none() {
+                    self.stop();
+                }
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+fn parse_mt940_statements(response: &RawMessage) -> Result<Vec<Transaction>, Error> {
+    let mut transactions = vec![];
+    for segment in response.find_all(Seg_HIKAZ_Statement::ID) {
+        let statement: Seg_HIKAZ_Statement = segment.deserialize()?;
+        transactions.extend(mt940::parse(&statement.booked, TransactionStatus::Booked)?);
+        if let Some(pending) = statement.pending {
+            transactions.extend(mt940::parse(&pending, TransactionStatus::Pending)?);
+        }
+    }
+    Ok(transactions)
+}
+
+fn parse_camt_statements(response: &RawMessage) -> Result<Vec<Transaction>, Error> {
+    let mut transactions = vec![];
+    for segment in response.find_all(Seg_HICAZ_CamtStatement::ID) {
+        let statement: Seg_HICAZ_CamtStatement = segment.deserialize()?;
+        for document in &statement.booked.statements {
+            transactions.extend(camt::parse(document, TransactionStatus::Booked)?);
+        }
+        if let Some(pending) = statement.pending {
+            transactions.extend(camt::parse(&pending, TransactionStatus::Pending)?);
+        }
+    }
+    Ok(transactions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    /// Answers with canned responses and keeps the messages sent, decrypted.
+    #[derive(Debug)]
+    struct MockTransport {
+        responses: VecDeque<Vec<u8>>,
+        sent: Rc<RefCell<Vec<RawMessage>>>,
+    }
+
+    impl Transport for MockTransport {
+        fn send(&mut self, _url: &str, message: &[u8]) -> Result<Vec<u8>, Error> {
+            self.sent
+                .borrow_mut()
+                .push(decrypt(RawMessage::from_bytes(message)?)?);
+            Ok(self.responses.pop_front().expect("No response left"))
+        }
+    }
+
+    const INIT_RESPONSE: &[u8] = b"HNHBK:1:3+000000000100+300+dialog1+1'\
+        HIRMG:2:2+0010::Nachricht entgegengenommen.'HNHBS:3:1+1'";
+    const END_RESPONSE: &[u8] =
+        b"HNHBK:1:3+000000000100+300+dialog1+2'HIRMG:2:2+0100::Dialog beendet.'HNHBS:3:1+2'";
+
+    /// A synchronized client talking to a `MockTransport`.
+    fn mock_client(responses: &[&[u8]]) -> (PinTanClient, Rc<RefCell<Vec<RawMessage>>>) {
+        let sent = Rc::new(RefCell::new(vec![]));
+        let mut client = PinTanClient {
+            url: "https://example.com".to_string(),
+            bank_code: 12345678,
+            username: "test1".to_string(),
+            pin: "1234".to_string(),
+            state: ClientState::default(),
+            transport: Box::new(MockTransport {
+                responses: responses.iter().map(|response| response.to_vec()).collect(),
+                sent: Rc::clone(&sent),
+            }),
+        };
+        client.state.customer_system_id = "abc".to_string();
+        (client, sent)
+    }
+
+    fn sent_identifiers(sent: &RefCell<Vec<RawMessage>>) -> Vec<Vec<String>> {
+        sent.borrow()
+            .iter()
+            .map(|message| {
+                message
+                    .segments
+                    .iter()
+                    .map(|segment| segment.identifier().to_string())
+                    .filter(|identifier| identifier.starts_with("HK"))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_open_dialog_drop() {
+        let (mut client, sent) = mock_client(&[INIT_RESPONSE, END_RESPONSE]);
+        let dialog = client.init_dialog().unwrap();
+        drop(dialog);
+        assert_eq!(
+            sent_identifiers(&sent),
+            vec![vec!["HKIDN", "HKVVB"], vec!["HKEND"]]
+        );
+        let end: Seg_HKEND_DialogEnd = sent.borrow()[1]
+            .find("HKEND")
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert_eq!(end.dialog_id, "dialog1");
+
+        // A failing job ends the dialog as well.
+        let error_response: &[u8] = b"HNHBK:1:3+000000000100+300+dialog1+2'\
+            HIRMG:2:2+9050::Die Nachricht enthaelt Fehler.'HNHBS:3:1+2'";
+        let (mut client, sent) = mock_client(&[INIT_RESPONSE, error_response, END_RESPONSE]);
+        let result = client
+            .init_dialog()
+            .and_then(|mut dialog| dialog.send_job(new_sepa_account_info()));
+        match result {
+            Err(Error::Bank { code, .. }) => assert_eq!(code, ReturnCode::MessageContainsErrors),
+            other => panic!("Expected rejection, got {:?}", other),
+        }
+        assert_eq!(
+            sent_identifiers(&sent),
+            vec![vec!["HKIDN", "HKVVB"], vec!["HKSPA"], vec!["HKEND"]]
+        );
+    }
+
+    #[test]
+    fn test_sepa_account() {
+        let response = RawMessage::from_bytes(
+            b"HISPA:4:1:3+J:DE00123456780001234567:TESTDEFFXXX:1234567::280:12345678\
+              +N::::7654321::280:12345678'",
+        )
+        .unwrap();
+        let sepa_account_info: Seg_HISPA_SepaAccountInfo1 =
+            response.segments[0].deserialize().unwrap();
+        let accounts: Vec<_> = sepa_account_info
+            .accounts
+            .into_iter()
+            .filter_map(SepaAccount::from_account_connection)
+            .collect();
+        assert_eq!(
+            accounts,
+            vec![SepaAccount {
+                iban: "DE00123456780001234567".to_string(),
+                bic: Some("TESTDEFFXXX".to_string()),
+                account_number: "1234567".to_string(),
+                sub_account: None,
+                country_code: "280".to_string(),
+                bank_code: 12345678,
+            }]
+        );
+
+        let connection = crate::se::to_string(&accounts[0].to_sepa_account_connection()).unwrap();
+        assert_eq!(
+            connection,
+            "J:DE00123456780001234567:TESTDEFFXXX:1234567::280:12345678"
+        );
+    }
+
+    #[test]
+    fn test_get_sepa_accounts() {
+        let init_response: &[u8] = b"HNHBK:1:3+000000000100+300+dialog1+1'\
+            HIRMG:2:2+0010::Nachricht entgegengenommen.'\
+            HIBPA:3:3:4+12+280:12345678+Testbank+3+1+300'\
+            HISPAS:4:1:4+1+1+0+J:N:N:urn?:iso?:std?:iso?:20022?:tech?:xsd?:pain.001.001.03'\
+            HISPAS:5:2:4+1+1+0+J:N:N:J:35:urn?:iso?:std?:iso?:20022?:tech?:xsd?:pain.001.001.03'\
+            HNHBS:6:1+1'";
+        let sepa_account_response: &[u8] = b"HNHBK:1:3+000000000100+300+dialog1+2'\
+            HIRMG:2:2+0010::Nachricht entgegengenommen.'\
+            HISPA:3:2:3+J:DE00123456780001234567:TESTDEFFXXX:1234567::280:12345678'\
+            HNHBS:4:1+2'";
+        let (mut client, sent) = mock_client(&[init_response, sepa_account_response, END_RESPONSE]);
+        let accounts = client.get_sepa_accounts().unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].iban, "DE00123456780001234567");
+        assert_eq!(
+            sent.borrow()[1].find("HKSPA").unwrap().version(),
+            Some(Seg_HKSPA_SepaAccountInfo2::VERSION)
+        );
+        assert_eq!(
+            sent_identifiers(&sent),
+            vec![vec!["HKIDN", "HKVVB"], vec!["HKSPA"], vec!["HKEND"]]
+        );
+    }
+
+    /// Initialization response with BPD announcing `HKSAL` and `HKKAZ` with a TAN required.
+    const TAN_INIT_RESPONSE: &[u8] = b"HNHBK:1:3+000000000100+300+dialog1+1'\
+        HIRMG:2:2+0010::Nachricht entgegengenommen.'\
+        HIBPA:3:3:4+12+280:12345678+Testbank+3+1+300'\
+        HIPINS:4:1:4+1+1+0+5:20:6:Benutzerkennung::HKSAL:J:HKKAZ:J'\
+        HITANS:5:6:4+1+1+0'\
+        HISALS:6:7:4+1+1+0'\
+        HIKAZS:7:7:4+1+1+0+90:N:N'\
+        HNHBS:8:1+1'";
+
+    /// Response asking for a TAN for the job in the second message of the dialog.
+    const TAN_REQUIRED_RESPONSE: &[u8] = b"HNHBK:1:3+000000000100+300+dialog1+2'\
+        HIRMG:2:2+3060::Bitte beachten Sie die Hinweise.'\
+        HIRMS:3:2:4+0030::Auftrag empfangen - Sicherheitsfreigabe erforderlich.'\
+        HITAN:4:6:4+4++ref123+Bitte TAN eingeben.'\
+        HNHBS:5:1+2'";
+
+    fn test_account() -> SepaAccount {
+        SepaAccount {
+            iban: "DE00123456780001234567".to_string(),
+            bic: Some("TESTDEFFXXX".to_string()),
+            account_number: "1234567".to_string(),
+            sub_account: None,
+            country_code: "280".to_string(),
+            bank_code: 12345678,
+        }
+    }
+
+    /// Response to HKKAZ with a single MT940 transaction and the touchdown point for the next
+    /// page if there is one.
+    fn statement_response(message_no: u16, purpose: &str, touchdown: Option<&str>) -> Vec<u8> {
+        let mt940 = format!(
+            ":20:STARTUMS\r\n:25:12345678/0001234567\r\n:28C:0\r\n:60F:C201230EUR1234,56\r\n\
+             :61:2101010101CR100,NTRFKREF1\r\n:86:{}\r\n:62F:C210101EUR1334,56\r\n-",
+            purpose
+        );
+        let feedback = match touchdown {
+            Some(touchdown) => format!("3040::Es liegen weitere Informationen vor.:{}", touchdown),
+            None => "0020::Auftrag ausgefuehrt.".to_string(),
+        };
+        format!(
+            "HNHBK:1:3+000000000100+300+dialog1+{no}'\
+             HIRMG:2:2+0010::Nachricht entgegengenommen.'\
+             HIRMS:3:2:4+{}'\
+             HIKAZ:4:7:4+@{}@{}'\
+             HNHBS:5:1+{no}'",
+            feedback,
+            mt940.len(),
+            mt940,
+            no = message_no
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn test_get_balance_with_tan() {
+        let tan_response: &[u8] = b"HNHBK:1:3+000000000100+300+dialog1+3'\
+            HIRMG:2:2+0010::Nachricht entgegengenommen.'\
+            HIRMS:3:2:3+0020::Auftrag ausgefuehrt.'\
+            HISAL:4:7:3+DE00123456780001234567:TESTDEFFXXX:1234567::280:12345678\
+            +Girokonto+EUR+C:1234,56:EUR:20201231'\
+            HNHBS:5:1+3'";
+        let (mut client, sent) = mock_client(&[
+            TAN_INIT_RESPONSE,
+            TAN_REQUIRED_RESPONSE,
+            tan_response,
+            END_RESPONSE,
+        ]);
+        client.state.tan_method = Some("942".to_string());
+
+        let challenge = match client.get_balance(&test_account()).unwrap() {
+            JobStatus::TanRequired(challenge) => challenge,
+            other => panic!("Expected TAN challenge, got {:?}", other),
+        };
+        assert_eq!(challenge.challenge, "Bitte TAN eingeben.");
+        let balance = challenge.submit("123456").unwrap();
+        assert_eq!(balance.booked, Decimal::new(123456, 2));
+
+        assert_eq!(
+            sent_identifiers(&sent),
+            vec![
+                vec!["HKIDN", "HKVVB", "HKTAN"],
+                vec!["HKSAL", "HKTAN"],
+                vec!["HKTAN"],
+                vec!["HKEND"],
+            ]
+        );
+        let tan_submission: Seg_HKTAN_TwoStepTanSubmission = sent.borrow()[2]
+            .find("HKTAN")
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert_eq!(tan_submission.job_reference.as_deref(), Some("ref123"));
+    }
+
+    #[test]
+    fn test_get_transactions_with_tan() {
+        let (mut client, sent) = mock_client(&[
+            TAN_INIT_RESPONSE,
+            TAN_REQUIRED_RESPONSE,
+            &statement_response(3, "Barauszahlung", None),
+            END_RESPONSE,
+        ]);
+        client.state.tan_method = Some("942".to_string());
+        let from = NaiveDate::from_ymd_opt(2021, 1, 1).unwrap();
+        let to = NaiveDate::from_ymd_opt(2021, 1, 31).unwrap();
+
+        let challenge = match client.get_transactions(&test_account(), from, to) {
+            Ok(JobStatus::TanRequired(challenge)) => challenge,
+            _ => panic!("Expected TAN challenge"),
+        };
+        let transactions = challenge.submit("123456").unwrap();
+        // The only page was received, so the dialog is ended right away.
+        assert_eq!(
+            sent_identifiers(&sent),
+            vec![
+                vec!["HKIDN", "HKVVB", "HKTAN"],
+                vec!["HKKAZ", "HKTAN"],
+                vec!["HKTAN"],
+                vec!["HKEND"],
+            ]
+        );
+        let transactions: Vec<_> = transactions.collect::<Result<_, _>>().unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].purpose.as_deref(), Some("Barauszahlung"));
+    }
+
+    #[test]
+    fn test_get_transactions_pages() {
+        let (mut client, sent) = mock_client(&[
+            TAN_INIT_RESPONSE,
+            &statement_response(2, "Seite 1", Some("page2")),
+            &statement_response(3, "Seite 2", None),
+            END_RESPONSE,
+        ]);
+        let from = NaiveDate::from_ymd_opt(2021, 1, 1).unwrap();
+        let to = NaiveDate::from_ymd_opt(2021, 1, 31).unwrap();
+
+        let transactions = match client.get_transactions(&test_account(), from, to) {
+            Ok(JobStatus::Executed(transactions)) => transactions,
+            _ => panic!("Expected transactions"),
+        };
+        let purposes: Vec<_> = transactions
+            .map(|transaction| transaction.unwrap().purpose.unwrap())
+            .collect();
+        assert_eq!(purposes, vec!["Seite 1", "Seite 2"]);
+
+        // The second page is requested with the touchdown point of the first one and the
+        // dialog is ended after the last page.
+        assert_eq!(
+            sent_identifiers(&sent),
+            vec![
+                vec!["HKIDN", "HKVVB"],
+                vec!["HKKAZ"],
+                vec!["HKKAZ"],
+                vec!["HKEND"],
+            ]
+        );
+        let touchdowns: Vec<_> = sent.borrow()[1..3]
+            .iter()
+            .map(|message| {
+                let request: Seg_HKKAZ_Statement7 =
+                    message.find("HKKAZ").unwrap().deserialize().unwrap();
+                request.touchdown
+            })
+            .collect();
+        assert_eq!(touchdowns, vec![None, Some("page2".to_string())]);
+    }
+
+    #[test]
+    fn test_get_transactions_page_error() {
+        let error_response: &[u8] = b"HNHBK:1:3+000000000100+300+dialog1+3'\
+            HIRMG:2:2+9050::Die Nachricht enthaelt Fehler.'\
+            HIRMS:3:2:4+9210::Aufsetzpunkt ungueltig.'\
+            HNHBS:4:1+3'";
+        let (mut client, sent) = mock_client(&[
+            TAN_INIT_RESPONSE,
+            &statement_response(2, "Seite 1", Some("page2")),
+            error_response,
+            END_RESPONSE,
+        ]);
+        let from = NaiveDate::from_ymd_opt(2021, 1, 1).unwrap();
+        let to = NaiveDate::from_ymd_opt(2021, 1, 31).unwrap();
+
+        let mut transactions = match client.get_transactions(&test_account(), from, to) {
+            Ok(JobStatus::Executed(transactions)) => transactions,
+            _ => panic!("Expected transactions"),
+        };
+        let transaction = transactions.next().unwrap().unwrap();
+        assert_eq!(transaction.purpose.as_deref(), Some("Seite 1"));
+        assert!(transactions.next().unwrap().is_err());
+        // No further pages are requested after an error and the dialog is ended.
+        assert!(transactions.next().is_none());
+        assert_eq!(
+            sent_identifiers(&sent),
+            vec![
+                vec!["HKIDN", "HKVVB"],
+                vec!["HKKAZ"],
+                vec!["HKKAZ"],
+                vec!["HKEND"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_transactions_pages_with_tan() {
+        // The bank waives the TAN for the second page with 3076 and asks for one for the third.
+        let second_page = String::from_utf8(statement_response(4, "Seite 2", Some("page3")))
+            .unwrap()
+            .replace(
+                "+3040::",
+                "+3076::Starke Kundenauthentifizierung nicht notwendig.+3040::",
+            );
+        let tan_required = String::from_utf8(TAN_REQUIRED_RESPONSE.to_vec())
+            .unwrap()
+            .replace("+2'", "+5'");
+        let (mut client, sent) = mock_client(&[
+            TAN_INIT_RESPONSE,
+            TAN_REQUIRED_RESPONSE,
+            &statement_response(3, "Seite 1", Some("page2")),
+            second_page.as_bytes(),
+            tan_required.as_bytes(),
+            &statement_response(6, "Seite 3", None),
+            END_RESPONSE,
+        ]);
+        client.state.tan_method = Some("942".to_string());
+        let from = NaiveDate::from_ymd_opt(2021, 1, 1).unwrap();
+        let to = NaiveDate::from_ymd_opt(2021, 1, 31).unwrap();
+
+        let challenge = match client.get_transactions(&test_account(), from, to) {
+            Ok(JobStatus::TanRequired(challenge)) => challenge,
+            _ => panic!("Expected TAN challenge"),
+        };
+        let mut transactions = challenge.submit("123456").unwrap();
+        let purpose = |transaction: Option<Result<Transaction, Error>>| {
+            transaction.unwrap().unwrap().purpose.unwrap()
+        };
+        assert_eq!(purpose(transactions.next()), "Seite 1");
+        assert_eq!(purpose(transactions.next()), "Seite 2");
+        match transactions.next() {
+            Some(Err(Error::TanRequired { text })) => assert_eq!(text, "Bitte TAN eingeben."),
+            other => panic!("Expected TAN required, got {:?}", other),
+        }
+        assert!(transactions.next().is_none());
+        assert_eq!(transactions.tan_challenge(), Some("Bitte TAN eingeben."));
+
+        transactions.submit_tan("654321").unwrap();
+        assert_eq!(transactions.tan_challenge(), None);
+        assert_eq!(purpose(transactions.next()), "Seite 3");
+        assert!(transactions.next().is_none());
+
+        // Every page is requested with HKTAN since the bank requires a TAN for HKKAZ.
+        assert_eq!(
+            sent_identifiers(&sent),
+            vec![
+                vec!["HKIDN", "HKVVB", "HKTAN"],
+                vec!["HKKAZ", "HKTAN"],
+                vec!["HKTAN"],
+                vec!["HKKAZ", "HKTAN"],
+                vec!["HKKAZ", "HKTAN"],
+                vec!["HKTAN"],
+                vec!["HKEND"],
+            ]
+        );
+        let touchdowns: Vec<_> = [1, 3, 4]
+            .iter()
+            .map(|&index| {
+                let request: Seg_HKKAZ_Statement7 = sent.borrow()[index]
+                    .find("HKKAZ")
+                    .unwrap()
+                    .deserialize()
+                    .unwrap();
+                request.touchdown
+            })
+            .collect();
+        assert_eq!(
+            touchdowns,
+            vec![None, Some("page2".to_string()), Some("page3".to_string())]
+        );
+        let tan_submission: Seg_HKTAN_TwoStepTanSubmission = sent.borrow()[5]
+            .find("HKTAN")
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert_eq!(tan_submission.job_reference.as_deref(), Some("ref123"));
+    }
+
+    #[test]
+    fn test_get_camt_transactions_with_tan() {
+        let init_response: &[u8] = b"HNHBK:1:3+000000000100+300+dialog1+1'\
+            HIRMG:2:2+0010::Nachricht entgegengenommen.'\
+            HIBPA:3:3:4+12+280:12345678+Testbank+3+1+300'\
+            HIPINS:4:1:4+1+1+0+5:20:6:Benutzerkennung::HKCAZ:J'\
+            HITANS:5:6:4+1+1+0'\
+            HICAZS:6:1:4+1+1+0+90:N:N:urn?:iso?:std?:iso?:20022?:tech?:xsd?:camt.052.001.02'\
+            HNHBS:7:1+1'";
+        let document = r#"<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.052.001.02">
+            <BkToCstmrAcctRpt><Rpt><Ntry>
+              <Amt Ccy="EUR">100.00</Amt><CdtDbtInd>CRDT</CdtDbtInd><Sts>BOOK</Sts>
+              <BookgDt><Dt>2021-01-01</Dt></BookgDt><ValDt><Dt>2021-01-01</Dt></ValDt>
+              <NtryDtls><TxDtls>
+                <Refs><EndToEndId>E2E-1</EndToEndId></Refs>
+                <RmtInf><Ustrd>Barauszahlung</Ustrd></RmtInf>
+              </TxDtls></NtryDtls>
+            </Ntry></Rpt></BkToCstmrAcctRpt>
+        </Document>"#;
+        let statement_response = format!(
+            "HNHBK:1:3+000000000100+300+dialog1+3'\
+             HIRMG:2:2+0010::Nachricht entgegengenommen.'\
+             HIRMS:3:2:4+0020::Auftrag ausgefuehrt.'\
+             HICAZ:4:1:4+DE00123456780001234567:TESTDEFFXXX\
+             +urn?:iso?:std?:iso?:20022?:tech?:xsd?:camt.052.001.02+@{}@{}'\
+             HNHBS:5:1+3'",
+            document.len(),
+            document
+        );
+        let (mut client, sent) = mock_client(&[
+            init_response,
+            TAN_REQUIRED_RESPONSE,
+            statement_response.as_bytes(),
+            END_RESPONSE,
+        ]);
+        client.state.tan_method = Some("942".to_string());
+        let from = NaiveDate::from_ymd_opt(2021, 1, 1).unwrap();
+        let to = NaiveDate::from_ymd_opt(2021, 1, 31).unwrap();
+
+        let challenge = match client.get_camt_transactions(&test_account(), from, to) {
+            Ok(JobStatus::TanRequired(challenge)) => challenge,
+            _ => panic!("Expected TAN challenge"),
+        };
+        let transactions = challenge.submit("123456").unwrap();
+        assert_eq!(
+            sent_identifiers(&sent),
+            vec![
+                vec!["HKIDN", "HKVVB", "HKTAN"],
+                vec!["HKCAZ", "HKTAN"],
+                vec!["HKTAN"],
+                vec!["HKEND"],
+            ]
+        );
+        let request: Seg_HKCAZ_CamtStatement = sent.borrow()[1]
+            .find("HKCAZ")
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert_eq!(
+            request.supported_camt_messages.descriptors,
+            vec!["urn:iso:std:iso:20022:tech:xsd:camt.052.001.02"]
+        );
+        let transactions: Vec<_> = transactions.collect::<Result<_, _>>().unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].purpose.as_deref(), Some("Barauszahlung"));
+        assert_eq!(transactions[0].customer_reference.as_deref(), Some("E2E-1"));
+    }
+
+    #[test]
+    fn test_transfer_decoupled_approval() {
+        let init_response: &[u8] = b"HNHBK:1:3+000000000100+300+dialog1+1'\
+            HIRMG:2:2+0010::Nachricht entgegengenommen.'\
+            HIBPA:3:3:4+12+280:12345678+Testbank+3+1+300'\
+            HIPINS:4:1:4+1+1+0+5:20:6:Benutzerkennung::HKCCS:J'\
+            HITANS:5:6:4+1+1+0'\
+            HISPAS:6:1:4+1+1+0+J:N:N:urn?:iso?:std?:iso?:20022?:tech?:xsd?:pain.001.001.03'\
+            HICCSS:7:1:4+1+1+0'\
+            HNHBS:8:1+1'";
+        let decoupled_response: &[u8] = b"HNHBK:1:3+000000000100+300+dialog1+2'\
+            HIRMG:2:2+3060::Bitte beachten Sie die Hinweise.'\
+            HIRMS:3:2:4+3955::Bitte bestaetigen Sie den Auftrag in Ihrer App.'\
+            HITAN:4:6:4+4++ref123+Bitte bestaetigen Sie den Auftrag in Ihrer App.'\
+            HNHBS:5:1+2'";
+        let (mut client, sent) = mock_client(&[init_response, decoupled_response, END_RESPONSE]);
+        client.state.tan_method = Some("942".to_string());
+        let recipient = Recipient {
+            name: "Max Mustermann".to_string(),
+            iban: "DE00765432100007654321".to_string(),
+            bic: None,
+        };
+
+        let result = client.transfer(
+            &test_account(),
+            &recipient,
+            Decimal::new(1000, 2),
+            "Rechnung 42",
+        );
+        // The transfer must not be reported as executed.
+        match result {
+            Err(Error::DecoupledApprovalRequired { text }) => {
+                assert_eq!(text, "Bitte bestaetigen Sie den Auftrag in Ihrer App.")
+            }
+            other => panic!("Expected decoupled approval, got {:?}", other),
+        }
+        assert_eq!(
+            sent_identifiers(&sent),
+            vec![
+                vec!["HKIDN", "HKVVB", "HKTAN"],
+                vec!["HKCCS", "HKTAN"],
+                vec!["HKEND"],
+            ]
+        );
+    }
 
-        let bytes = base64::decode(&response.text()?)?;
-        let (decoded, _, had_errors) = ISO_8859_15.decode(&bytes);
+    #[test]
+    fn test_check_batch_limits() {
+        let response = RawMessage::from_bytes(b"HICCMS:12:1:4+1+1+0+2:J:N'").unwrap();
+        let parameters: Seg_HICCMS_SepaBatchTransferParameters =
+            response.segments[0].deserialize().unwrap();
+        let parameters = parameters.parameters;
+        assert!(parameters.sum_amount_required);
 
-        println!("response {} {}", response.status(), decoded);
-        Result::Ok(response.text()?)
+        assert!(check_batch_limits(&parameters, 2, false).is_ok());
+        match check_batch_limits(&parameters, 3, false) {
+            Err(Error::LimitExceeded(limit)) => {
+                assert_eq!(limit, "3 transfers in a batch, at most 2 allowed")
+            }
+            other => panic!("Expected exceeded limit, got {:?}", other),
+        }
+        assert!(check_batch_limits(&parameters, 1, true).is_err());
     }
 }
@@ -1,17 +1,27 @@
 use chrono::{NaiveDate, NaiveTime};
+use fints_derive::DataElementGroup;
+use rust_decimal::Decimal;
 use serde_derive::{Deserialize, Serialize};
-use serde_repr::{Serialize_repr, Deserialize_repr};
+use serde_repr::{Deserialize_repr, Serialize_repr};
 use std::fmt;
 
+/// A data element group.
+///
+/// This is implemented by `#[derive(DataElementGroup)]`.
+pub trait DataElementGroup {
+    /// Number of components the DEG takes up, including those of nested DEGs.
+    const WIDTH: usize;
+}
+
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, DataElementGroup)]
 pub struct DEG_InstituteIdentifier {
     pub country_code: String,
     pub bank_code: u32,
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, DataElementGroup)]
 pub struct DEG_SegmentHead {
     pub identifier: String,
     pub segment_no: u16,
@@ -19,9 +29,9 @@ pub struct DEG_SegmentHead {
     pub reference_seg: Option<u16>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, DataElementGroup)]
 pub struct ReferenceMessage {
-    pub dialog_id: u16,
+    pub dialog_id: String,
     pub message_no: u16,
 }
 
@@ -41,19 +51,49 @@ impl fmt::Display for SecurityMethodCode {
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, DataElementGroup)]
 pub struct DEG_SecurityProfile {
     pub security_method_code: SecurityMethodCode,
     pub version: u8,
 }
 
-#[derive(Debug, Serialize_repr, Deserialize_repr)]
-#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "u16", into = "u16")]
 pub enum SecurityFunction {
-    NRO = 1,
-    AUT = 2,
-    ENC = 4,
-    SingleStepAuth = 999,
+    NRO,
+    AUT,
+    ENC,
+    PinTanEncryption,
+    SingleStepAuth,
+
+    /// A two-step TAN method announced by the bank, e.g. `920`.
+    TwoStepAuth(u16),
+}
+
+impl From<u16> for SecurityFunction {
+    fn from(code: u16) -> SecurityFunction {
+        match code {
+            1 => SecurityFunction::NRO,
+            2 => SecurityFunction::AUT,
+            4 => SecurityFunction::ENC,
+            998 => SecurityFunction::PinTanEncryption,
+            999 => SecurityFunction::SingleStepAuth,
+            code => SecurityFunction::TwoStepAuth(code),
+        }
+    }
+}
+
+impl From<SecurityFunction> for u16 {
+    fn from(security_function: SecurityFunction) -> u16 {
+        match security_function {
+            SecurityFunction::NRO => 1,
+            SecurityFunction::AUT => 2,
+            SecurityFunction::ENC => 4,
+            SecurityFunction::PinTanEncryption => 998,
+            SecurityFunction::SingleStepAuth => 999,
+            SecurityFunction::TwoStepAuth(code) => code,
+        }
+    }
 }
 
 #[derive(Debug, Serialize_repr, Deserialize_repr)]
@@ -79,9 +119,10 @@ pub enum SecurityPartyIdentifier {
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, DataElementGroup)]
 pub struct DEG_SecurityIdentificationDetails {
     pub security_party_identifier: SecurityPartyIdentifier,
+    #[serde(with = "serde_bytes")]
     pub cardholder_identification: Option<Vec<u8>>,
     pub party_identifier: Option<String>,
 }
@@ -97,7 +138,7 @@ pub enum DateIdentifier {
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, DataElementGroup)]
 pub struct DEG_SecurityDate {
     // Datum- und Zeitbezeichner, kodiert
     pub date_identifier: DateIdentifier,
@@ -116,7 +157,7 @@ mod fints_date_format {
     use serde::{self, Deserialize, Deserializer, Serializer};
 
     /// ISO 8601
-    const FORMAT: &'static str = "%Y%m%d";
+    const FORMAT: &str = "%Y%m%d";
 
     pub fn serialize<S>(date: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -140,7 +181,7 @@ mod fints_time_format {
     use serde::{self, Deserialize, Deserializer, Serializer};
 
     /// ISO 8601
-    const FORMAT: &'static str = "%H%M%S";
+    const FORMAT: &str = "%H%M%S";
 
     pub fn serialize<S>(time: &NaiveTime, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -159,6 +200,82 @@ mod fints_time_format {
     }
 }
 
+pub(crate) mod fints_optional_date_format {
+    use chrono::NaiveDate;
+    use serde::{self, Deserialize, Deserializer, Serializer};
+
+    const FORMAT: &str = "%Y%m%d";
+
+    pub fn serialize<S>(date: &Option<NaiveDate>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match date {
+            Some(date) => serializer.serialize_some(&date.format(FORMAT).to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<NaiveDate>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|s| NaiveDate::parse_from_str(&s, FORMAT).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+pub(crate) mod fints_optional_time_format {
+    use chrono::NaiveTime;
+    use serde::{self, Deserialize, Deserializer, Serializer};
+
+    const FORMAT: &str = "%H%M%S";
+
+    pub fn serialize<S>(time: &Option<NaiveTime>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match time {
+            Some(time) => serializer.serialize_some(&time.format(FORMAT).to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<NaiveTime>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|s| NaiveTime::parse_from_str(&s, FORMAT).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+/// Amounts use a decimal comma and no thousands separator.
+mod fints_decimal_format {
+    use rust_decimal::Decimal;
+    use serde::{self, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(value: &Decimal, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let s = value.to_string().replace('.', ",");
+        serializer.serialize_str(&s)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Decimal, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.replace(',', ".")
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(Debug, Serialize_repr, Deserialize_repr)]
 #[repr(u16)]
 pub enum UseOfHashAlgorithm {
@@ -187,7 +304,7 @@ pub enum HashAlgorithmParameterIdentifier {
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, DataElementGroup)]
 pub struct DEG_HashAlgorithm {
     // Verwendung des Hashalgorithmus, kodiert
     pub use_of_hash_algorithm: UseOfHashAlgorithm,
@@ -199,6 +316,7 @@ pub struct DEG_HashAlgorithm {
     pub hash_algorithm_param_identifier: HashAlgorithmParameterIdentifier,
 
     // Wert des Hashalgorithmusparameters
+    #[serde(with = "serde_bytes")]
     pub param_value: Option<Vec<u8>>,
 }
 
@@ -244,7 +362,7 @@ pub enum OperationMode {
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, DataElementGroup)]
 pub struct DEG_SignatureAlgorithm {
     // Verwendung des Signaturalgorithmus, kodiert
     pub use_of_signature_algorithm: UseOfSignatureAlgorithm,
@@ -256,6 +374,98 @@ pub struct DEG_SignatureAlgorithm {
     pub operation_mode: OperationMode,
 }
 
+#[derive(Debug, Serialize_repr, Deserialize_repr)]
+#[repr(u16)]
+pub enum UseOfEncryptionAlgorithm {
+    // Owner Symmetric
+    OSY = 2,
+}
+
+#[derive(Debug, Serialize_repr, Deserialize_repr)]
+#[repr(u16)]
+pub enum EncryptionAlgorithm {
+    // 2-Key-Triple-DES
+    TwoKeyTripleDES = 13,
+
+    // AES-256
+    AES256 = 14,
+}
+
+#[derive(Debug, Serialize_repr, Deserialize_repr)]
+#[repr(u16)]
+pub enum KeyParameterIdentifier {
+    // Symmetrischer Schlüssel, verschlüsselt mit symmetrischem Schlüssel
+    KYE = 5,
+
+    // Symmetrischer Schlüssel, verschlüsselt mit öffentlichem Schlüssel
+    KYP = 6,
+}
+
+#[derive(Debug, Serialize_repr, Deserialize_repr)]
+#[repr(u16)]
+pub enum IvParameterIdentifier {
+    // Initialization value, clear text
+    IVC = 1,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_EncryptionAlgorithm {
+    // Verwendung des Verschlüsselungsalgorithmus, kodiert
+    pub use_of_encryption_algorithm: UseOfEncryptionAlgorithm,
+
+    // Operationsmodus, kodiert
+    pub operation_mode: OperationMode,
+
+    // Verschlüsselungsalgorithmus, kodiert
+    pub encryption_algorithm: EncryptionAlgorithm,
+
+    // Wert des Algorithmusparameters, Schlüssel
+    #[serde(with = "serde_bytes")]
+    pub key_param_value: Vec<u8>,
+
+    // Bezeichner für Algorithmusparameter, Schlüssel
+    pub key_param_identifier: KeyParameterIdentifier,
+
+    // Bezeichner für Algorithmusparameter, IV
+    pub iv_param_identifier: IvParameterIdentifier,
+
+    // Wert des Algorithmusparameters, IV
+    #[serde(with = "serde_bytes")]
+    pub iv_param_value: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Serialize_repr, Deserialize_repr)]
+#[repr(u16)]
+pub enum CompressionFunction {
+    // Keine Kompression
+    NULL = 0,
+
+    // Lempel, Ziv, Welch
+    LZW = 1,
+
+    // Optimized LZW
+    COM = 2,
+
+    // Lempel, Ziv
+    LZSS = 3,
+
+    // LZ + Huffman Coding
+    LZHuf = 4,
+
+    // PKZIP
+    ZIP = 5,
+
+    // deflate (http://www.gzip.org/zlib)
+    GZIP = 6,
+
+    // bzip2 (http://sourceware.cygnus.com/bzip2/)
+    BZIP2 = 7,
+
+    // Gegenseitig vereinbart
+    ZZZ = 999,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum KeyType {
     // Schlüssel zur Erzeugung digitaler Signaturen (DS-Schlüssel)
@@ -269,9 +479,10 @@ pub enum KeyType {
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, DataElementGroup)]
 pub struct DEG_KeyName {
     // Kreditinstitutskennung
+    #[fints(deg)]
     pub institute_identifier: DEG_InstituteIdentifier,
 
     // Benutzerkennung
@@ -302,12 +513,13 @@ pub enum CertificateType {
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, DataElementGroup)]
 pub struct DEG_Certificate {
     // Zertifikatstyp
     pub certificate_type: CertificateType,
 
     // Zertifikatsinhalt
+    #[serde(with = "serde_bytes")]
     pub value: Vec<u8>,
 }
 
@@ -339,15 +551,18 @@ pub enum TanProcess {
     // Alle
     All = 0,
 
-    // Aktiv
-    Active = 2,
+    // Zwei-Schritt-Verfahren, Schritt 2: Einreichung der TAN
+    TanSubmission = 2,
 
     // Verfügbar
     Available = 3,
+
+    // Zwei-Schritt-Verfahren, Schritt 1: Einreichung des Auftrags
+    JobSubmission = 4,
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, DataElementGroup)]
 pub struct DEG_AccountInternationalIssuer {
     pub iban: String,
 }
@@ -385,7 +600,7 @@ pub enum FunctionTypeIdentifier {
 
 #[allow(non_camel_case_types)]
 #[allow(non_snake_case)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, DataElementGroup)]
 pub struct DEG_UserDefinedSignature {
     pub PIN: String,
     pub TAN: Option<String>,
@@ -398,3 +613,559 @@ pub enum SynchronizationMode {
     ReportLastProcessedMessageNo = 1,
     ReportSignatureId = 2,
 }
+
+/// Category of a [`ReturnCode`], given by its first digit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReturnCodeCategory {
+    // 0xxx
+    Success,
+
+    // 3xxx
+    Warning,
+
+    // 9xxx
+    Error,
+}
+
+macro_rules! return_codes {
+    ($($variant:ident = $code:expr,)*) => {
+        /// Return codes (Rückmeldungscodes) reported through HIRMG and HIRMS.
+        ///
+        /// Codes without a variant of their own end up in `Other`.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub enum ReturnCode {
+            $($variant,)*
+            Other(u16),
+        }
+
+        impl ReturnCode {
+            pub fn from_code(code: u16) -> ReturnCode {
+                match code {
+                    $($code => ReturnCode::$variant,)*
+                    code => ReturnCode::Other(code),
+                }
+            }
+
+            pub fn code(self) -> u16 {
+                match self {
+                    $(ReturnCode::$variant => $code,)*
+                    ReturnCode::Other(code) => code,
+                }
+            }
+        }
+    };
+}
+
+return_codes! {
+    // Nachricht entgegengenommen
+    MessageReceived = 10,
+
+    // Auftrag ausgeführt
+    Executed = 20,
+
+    // Auftrag empfangen - Sicherheitsfreigabe erforderlich
+    TanRequired = 30,
+
+    // Dialog beendet
+    DialogEnded = 100,
+
+    // Es liegen weitere Informationen vor
+    MoreDataAvailable = 3040,
+
+    // UPD nicht mehr aktuell, aktuelle Version enthalten
+    UpdOutdated = 3050,
+
+    // Bitte beachten Sie die enthaltenen Warnungen/Hinweise
+    SeeWarnings = 3060,
+
+    // Starke Kundenauthentifizierung nicht notwendig
+    StrongAuthenticationNotRequired = 3076,
+
+    // Zugelassene Zwei-Schritt-Verfahren für den Benutzer
+    AllowedTanMethods = 3920,
+
+    // Sicherheitsfreigabe erfolgt über anderen Kanal
+    SecurityClearanceViaOtherChannel = 3955,
+
+    // Starke Kundenauthentifizierung noch ausstehend
+    StrongAuthenticationPending = 3956,
+
+    // Verarbeitung nicht möglich
+    ProcessingNotPossible = 9010,
+
+    // Die Nachricht enthält Fehler
+    MessageContainsErrors = 9050,
+
+    // Dialog abgebrochen
+    DialogAborted = 9800,
+
+    // Sperrung des Kontos nach Fehlversuchen
+    AccessBlocked = 9931,
+
+    // TAN ungültig
+    InvalidTan = 9941,
+
+    // PIN falsch
+    InvalidPin = 9942,
+}
+
+impl ReturnCode {
+    pub fn category(self) -> ReturnCodeCategory {
+        match self.code() {
+            9000..=9999 => ReturnCodeCategory::Error,
+            3000..=8999 => ReturnCodeCategory::Warning,
+            _ => ReturnCodeCategory::Success,
+        }
+    }
+
+    /// Whether the job is only carried out once it's confirmed, e.g. with a TAN.
+    pub fn requires_tan(self) -> bool {
+        matches!(
+            self,
+            ReturnCode::TanRequired | ReturnCode::SecurityClearanceViaOtherChannel
+        )
+    }
+}
+
+impl fmt::Display for ReturnCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}", self.code())
+    }
+}
+
+impl serde::Serialize for ReturnCode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for ReturnCode {
+    fn deserialize<D>(deserializer: D) -> Result<ReturnCode, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+        s.parse()
+            .map(ReturnCode::from_code)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Rückmeldung
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_Feedback {
+    // Rückmeldungscode
+    pub code: ReturnCode,
+
+    // Bezugsdatenelement
+    pub reference_element: Option<String>,
+
+    // Rückmeldungstext
+    pub text: String,
+
+    // Rückmeldungsparameter
+    #[fints(max = 10)]
+    pub parameters: Vec<String>,
+}
+
+/// Unterstützte Sprachen
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_SupportedLanguages {
+    #[fints(max = 9)]
+    pub languages: Vec<DialogLang>,
+}
+
+/// Unterstützte HBCI-Versionen
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_SupportedHbciVersions {
+    #[fints(max = 9)]
+    pub versions: Vec<u16>,
+}
+
+#[derive(Debug, Serialize_repr, Deserialize_repr)]
+#[repr(u16)]
+pub enum CommunicationService {
+    // TCP/IP (SLIP/PPP)
+    TcpIp = 2,
+
+    // HTTPS
+    Https = 3,
+}
+
+/// Kommunikationsparameter
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_CommunicationParameters {
+    // Kommunikationsdienst
+    pub service: CommunicationService,
+
+    // Kommunikationsadresse
+    pub address: String,
+
+    // Kommunikationsadressenzusatz
+    pub address_suffix: Option<String>,
+
+    // Filterfunktion
+    pub filter_function: Option<String>,
+
+    // Version der Filterfunktion
+    pub filter_function_version: Option<u16>,
+}
+
+/// Unterstützte Sicherheitsverfahren
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_SecurityMethods {
+    // Sicherheitsverfahren, Code
+    pub security_method_code: String,
+
+    // Versionen
+    #[fints(max = 9)]
+    pub versions: Vec<u16>,
+}
+
+/// Geschäftsvorfallspezifische PIN/TAN-Informationen
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_JobTanRequirement {
+    // Segmentkennung
+    pub segment_identifier: String,
+
+    // TAN erforderlich
+    pub tan_required: bool,
+}
+
+/// Parameter PIN/TAN-spezifische Informationen
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_PinTanInformation {
+    // Minimale PIN-Länge
+    pub min_pin_length: Option<u16>,
+
+    // Maximale PIN-Länge
+    pub max_pin_length: Option<u16>,
+
+    // Maximale TAN-Länge
+    pub max_tan_length: Option<u16>,
+
+    // Text zur Belegung der Benutzerkennung
+    pub user_id_text: Option<String>,
+
+    // Text zur Belegung der Kunden-ID
+    pub customer_id_text: Option<String>,
+
+    // Geschäftsvorfallspezifische PIN/TAN-Informationen
+    #[fints(deg, max = 999)]
+    pub jobs: Vec<DEG_JobTanRequirement>,
+}
+
+#[derive(Debug, Serialize_repr, Deserialize_repr)]
+#[repr(u16)]
+pub enum UpdUsage {
+    // Die nicht aufgeführten Geschäftsvorfälle sind gesperrt
+    UnlistedJobsBlocked = 0,
+
+    // Bei nicht aufgeführten Geschäftsvorfällen ist keine Aussage möglich, ob diese erlaubt
+    // oder gesperrt sind
+    UnlistedJobsUnknown = 1,
+}
+
+/// Kontoverbindung
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_AccountConnection {
+    // Konto-/Depotnummer
+    pub account_number: String,
+
+    // Unterkontomerkmal
+    pub sub_account: Option<String>,
+
+    // Kreditinstitutskennung
+    #[fints(deg)]
+    pub institute_identifier: DEG_InstituteIdentifier,
+}
+
+/// Betrag
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_Amount {
+    // Wert
+    #[serde(with = "fints_decimal_format")]
+    pub value: Decimal,
+
+    // Währung
+    pub currency: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum LimitType {
+    // Einzelauftragslimit
+    E,
+
+    // Tageslimit
+    T,
+
+    // Wochenlimit
+    W,
+
+    // Monatslimit
+    M,
+
+    // Zeitlimit
+    Z,
+}
+
+/// Kontolimit
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_AccountLimit {
+    // Limitart
+    pub limit_type: LimitType,
+
+    // Limitbetrag
+    #[fints(deg)]
+    pub amount: Option<DEG_Amount>,
+
+    // Limit-Tage
+    pub limit_days: Option<u16>,
+}
+
+/// Erlaubte Geschäftsvorfälle
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_AllowedJob {
+    // Geschäftsvorfall
+    pub job: String,
+
+    // Anzahl benötigter Signaturen
+    pub required_signatures: u16,
+
+    // Limitart
+    pub limit_type: Option<LimitType>,
+
+    // Limitbetrag
+    #[fints(deg)]
+    pub amount: Option<DEG_Amount>,
+
+    // Limit-Tage
+    pub limit_days: Option<u16>,
+}
+
+/// Kontoverbindung ZV
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_SepaAccountConnection {
+    // IBAN-fähig
+    pub is_sepa: bool,
+
+    // IBAN
+    pub iban: Option<String>,
+
+    // BIC
+    pub bic: Option<String>,
+
+    // Konto-/Depotnummer
+    pub account_number: String,
+
+    // Unterkontomerkmal
+    pub sub_account: Option<String>,
+
+    // Kreditinstitutskennung
+    #[fints(deg)]
+    pub institute_identifier: DEG_InstituteIdentifier,
+}
+
+/// Kontoverbindung international
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_InternationalAccountConnection {
+    // IBAN
+    pub iban: Option<String>,
+
+    // BIC
+    pub bic: Option<String>,
+
+    // Konto-/Depotnummer
+    pub account_number: Option<String>,
+
+    // Unterkontomerkmal
+    pub sub_account: Option<String>,
+
+    // Kreditinstitutskennung
+    #[fints(deg)]
+    pub institute_identifier: Option<DEG_InstituteIdentifier>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CreditDebit {
+    // Haben
+    C,
+
+    // Soll
+    D,
+}
+
+/// Saldo
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_Balance {
+    // Soll-Haben-Kennzeichen
+    pub credit_debit: CreditDebit,
+
+    // Betrag
+    #[fints(deg)]
+    pub amount: DEG_Amount,
+
+    // Datum
+    #[serde(with = "fints_date_format")]
+    pub date: NaiveDate,
+
+    // Uhrzeit
+    #[serde(with = "fints_optional_time_format")]
+    pub time: Option<NaiveTime>,
+}
+
+impl DEG_Balance {
+    /// The amount, negative for debit balances.
+    pub fn signed_value(&self) -> Decimal {
+        match self.credit_debit {
+            CreditDebit::C => self.amount.value,
+            CreditDebit::D => -self.amount.value,
+        }
+    }
+}
+
+/// Zeitstempel
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_Timestamp {
+    // Datum
+    #[serde(with = "fints_date_format")]
+    pub date: NaiveDate,
+
+    // Uhrzeit
+    #[serde(with = "fints_optional_time_format")]
+    pub time: Option<NaiveTime>,
+}
+
+/// Unterstützte camt-Messages
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_SupportedCamtMessages {
+    // camt-Deskriptor
+    #[fints(max = 99)]
+    pub descriptors: Vec<String>,
+}
+
+/// Gebuchte camt-Umsätze
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_BookedCamtStatements {
+    // Gebuchte Umsätze
+    #[fints(max = 999)]
+    pub statements: Vec<serde_bytes::ByteBuf>,
+}
+
+/// Parameter Kontoumsätze/Zeitraum camt
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_CamtStatementParameters {
+    // Speicherzeitraum
+    pub storage_period: u16,
+
+    // Eingabe Anzahl Einträge erlaubt
+    pub max_entries_allowed: bool,
+
+    // Alle Konten
+    pub all_accounts_allowed: bool,
+
+    // Unterstützte camt-Messages
+    #[fints(deg)]
+    pub supported_camt_messages: DEG_SupportedCamtMessages,
+}
+
+/// Parameter SEPA-Kontoverbindung anfordern, Version 1
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_SepaAccountParameters1 {
+    // Einzelkontoabruf erlaubt
+    pub single_account_query_allowed: bool,
+
+    // Nationale Kontoverbindung erlaubt
+    pub national_account_allowed: bool,
+
+    // Strukturierter Verwendungszweck erlaubt
+    pub structured_purpose_allowed: bool,
+
+    // Unterstützte SEPA-Datenformate
+    #[fints(max = 99)]
+    pub supported_sepa_formats: Vec<String>,
+}
+
+/// Parameter SEPA-Kontoverbindung anfordern, Version 2
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_SepaAccountParameters2 {
+    // Einzelkontoabruf erlaubt
+    pub single_account_query_allowed: bool,
+
+    // Nationale Kontoverbindung erlaubt
+    pub national_account_allowed: bool,
+
+    // Strukturierter Verwendungszweck erlaubt
+    pub structured_purpose_allowed: bool,
+
+    // Eingabe Anzahl Einträge erlaubt
+    pub max_entries_allowed: bool,
+
+    // Anzahl reservierter Verwendungszweckstellen
+    pub reserved_purpose_length: Option<u16>,
+
+    // Unterstützte SEPA-Datenformate
+    #[fints(max = 99)]
+    pub supported_sepa_formats: Vec<String>,
+}
+
+/// Parameter SEPA-Sammelüberweisung
+#[allow(non_camel_case_types)]
+#[derive(Debug, DataElementGroup)]
+pub struct DEG_BatchTransferParameters {
+    // Maximale Anzahl CreditTransferTransactionInformation
+    pub max_transfer_count: u32,
+
+    // Summenfeld benötigt
+    pub sum_amount_required: bool,
+
+    // Einzelbuchung erlaubt
+    pub single_booking_allowed: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_return_code() {
+        assert_eq!(ReturnCode::from_code(20), ReturnCode::Executed);
+        assert_eq!(ReturnCode::from_code(9999), ReturnCode::Other(9999));
+        assert_eq!(ReturnCode::Executed.to_string(), "0020");
+        assert_eq!(
+            ReturnCode::Other(9999).category(),
+            ReturnCodeCategory::Error
+        );
+        assert_eq!(
+            ReturnCode::DialogEnded.category(),
+            ReturnCodeCategory::Success
+        );
+        assert!(ReturnCode::from_code(30).requires_tan());
+        assert!(ReturnCode::from_code(3955).requires_tan());
+        assert!(!ReturnCode::Executed.requires_tan());
+    }
+}
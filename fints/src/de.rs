@@ -0,0 +1,1107 @@
+//! Deserialization.
+//!
+//! A FinTS message is parsed in two steps. First, the raw bytes are split into segments, data
+//! elements and components while honoring `?` escaping and `@len@` binary blocks. The result
+//! is a [`RawMessage`]. Then, individual segments (or whole messages) can be deserialized into
+//! types deriving `Message`, `Segment` or `DataElementGroup`.
+
+use crate::data_types::DataElementGroup;
+use crate::segments::Segment;
+use crate::utils::{unescape_fints, StructKind};
+use encoding_rs::ISO_8859_15;
+use log::{info, trace};
+use serde::de::{
+    self, Deserialize, DeserializeOwned, DeserializeSeed, IntoDeserializer, SeqAccess, Visitor,
+};
+use std::fmt::{self, Display};
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error(String);
+type Result<T> = std::result::Result<T, Error>;
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A single value as it appears on the wire with all escaping already removed.
+#[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
+pub enum Value {
+    /// Any non-binary value. Numbers, dates and codes are kept as text until they are
+    /// deserialized into their target type.
+    Text(String),
+
+    /// A binary value which was framed as `@<len>@<bytes>` on the wire.
+    Binary(Vec<u8>),
+}
+
+impl Value {
+    fn is_empty(&self) -> bool {
+        match self {
+            Value::Text(text) => text.is_empty(),
+            Value::Binary(_) => false,
+        }
+    }
+
+    fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text(text) => Some(text),
+            Value::Binary(_) => None,
+        }
+    }
+}
+
+/// A segment split into its DEs, each of which consists of one or more `:`-delimited
+/// components.
+#[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
+pub struct RawSegment {
+    pub elements: Vec<Vec<Value>>,
+}
+
+impl RawSegment {
+    fn head(&self, index: usize) -> Option<&str> {
+        self.elements.first()?.get(index)?.as_text()
+    }
+
+    /// Segment identifier such as `HNHBK` or `HIRMG`.
+    pub fn identifier(&self) -> &str {
+        self.head(0).unwrap_or_default()
+    }
+
+    /// Segment number.
+    pub fn segment_no(&self) -> Option<u16> {
+        self.head(1)?.parse().ok()
+    }
+
+    /// Segment version.
+    pub fn version(&self) -> Option<u16> {
+        self.head(2)?.parse().ok()
+    }
+
+    /// Number of the segment this segment refers to (only set in responses).
+    pub fn reference_seg(&self) -> Option<u16> {
+        self.head(3)?.parse().ok()
+    }
+
+    /// First component of the DE at `index` as text. The segment head is the DE at index `0`.
+    pub fn element(&self, index: usize) -> Option<&str> {
+        self.elements.get(index)?.first()?.as_text()
+    }
+
+    /// Deserialize this segment into a typed segment struct with the same identifier.
+    pub fn deserialize<T>(&self) -> Result<T>
+    where
+        T: DeserializeOwned + Segment,
+    {
+        if self.identifier() != T::ID {
+            return Err(Error(format!(
+                "Expected segment {}, found {}",
+                T::ID,
+                self.identifier()
+            )));
+        }
+        from_segments(std::slice::from_ref(self))
+    }
+}
+
+/// A parsed but otherwise uninterpreted FinTS message.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RawMessage {
+    pub segments: Vec<RawSegment>,
+}
+
+impl RawMessage {
+    /// Parse a message as it was received from the bank, i.e. ISO 8859-15 encoded.
+    pub fn from_bytes(input: &[u8]) -> Result<RawMessage> {
+        Ok(RawMessage {
+            segments: tokenize(input)?,
+        })
+    }
+
+    /// First segment with the given identifier.
+    pub fn find(&self, identifier: &str) -> Option<&RawSegment> {
+        self.segments
+            .iter()
+            .find(|segment| segment.identifier() == identifier)
+    }
+
+    /// All segments with the given identifier in the order they were received.
+    pub fn find_all<'a>(&'a self, identifier: &'a str) -> impl Iterator<Item = &'a RawSegment> {
+        self.segments
+            .iter()
+            .filter(move |segment| segment.identifier() == identifier)
+    }
+}
+
+fn decode(bytes: &[u8]) -> String {
+    ISO_8859_15
+        .decode_without_bom_handling(bytes)
+        .0
+        .into_owned()
+}
+
+/// Finish the component that is currently being read.
+fn take_value(current: &mut Vec<u8>, binary: &mut Option<Vec<u8>>) -> Result<Value> {
+    match binary.take() {
+        Some(_) if !current.is_empty() => Err(Error(format!(
+            "Unexpected data after binary value: {:?}",
+            decode(current)
+        ))),
+        Some(data) => Ok(Value::Binary(data)),
+        None => {
+            let text = unescape_fints(&decode(current));
+            current.clear();
+            Ok(Value::Text(text))
+        }
+    }
+}
+
+/// Split `input` into segments, DEs and components.
+fn tokenize(input: &[u8]) -> Result<Vec<RawSegment>> {
+    let mut segments = vec![];
+    let mut elements = vec![];
+    let mut components = vec![];
+    let mut current = vec![];
+    let mut binary: Option<Vec<u8>> = None;
+
+    let mut pos = 0;
+    while pos < input.len() {
+        let byte = input[pos];
+        match byte {
+            b'?' => {
+                // Keep the escape sequence around, it's removed once the value is complete.
+                let escaped = input
+                    .get(pos + 1)
+                    .ok_or_else(|| Error("Escape character at end of input".to_string()))?;
+                current.push(byte);
+                current.push(*escaped);
+                pos += 2;
+                continue;
+            }
+            b'@' if current.is_empty() && binary.is_none() => {
+                let len_end = input[pos + 1..]
+                    .iter()
+                    .position(|&b| b == b'@')
+                    .map(|offset| pos + 1 + offset)
+                    .ok_or_else(|| Error("Unterminated binary length".to_string()))?;
+                let len: usize = decode(&input[pos + 1..len_end])
+                    .parse()
+                    .map_err(|e| Error(format!("Invalid binary length: {}", e)))?;
+                let data_end = len_end
+                    .checked_add(1)
+                    .and_then(|start| start.checked_add(len))
+                    .ok_or_else(|| Error(format!("Invalid binary length: {}", len)))?;
+                let data = input
+                    .get(len_end + 1..data_end)
+                    .ok_or_else(|| Error(format!("Binary value shorter than {} bytes", len)))?;
+                binary = Some(data.to_vec());
+                pos = data_end;
+                continue;
+            }
+            b':' => {
+                components.push(take_value(&mut current, &mut binary)?);
+            }
+            b'+' => {
+                components.push(take_value(&mut current, &mut binary)?);
+                elements.push(std::mem::take(&mut components));
+            }
+            b'\'' => {
+                components.push(take_value(&mut current, &mut binary)?);
+                elements.push(std::mem::take(&mut components));
+                segments.push(RawSegment {
+                    elements: std::mem::take(&mut elements),
+                });
+            }
+            // Some servers put line breaks between segments.
+            b'\r' | b'\n'
+                if current.is_empty()
+                    && binary.is_none()
+                    && components.is_empty()
+                    && elements.is_empty() => {}
+            _ => current.push(byte),
+        }
+        pos += 1;
+    }
+
+    // Be lenient about a missing terminator on the last segment so that single DEGs can be
+    // parsed on their own as well.
+    if !current.is_empty() || binary.is_some() || !components.is_empty() || !elements.is_empty() {
+        components.push(take_value(&mut current, &mut binary)?);
+        elements.push(components);
+        segments.push(RawSegment { elements });
+    }
+
+    trace!("Tokenized {} segments", segments.len());
+    Ok(segments)
+}
+
+pub struct Deserializer<'a> {
+    /// All segments available to the deserializer.
+    segments: &'a [RawSegment],
+
+    /// The segment we're currently reading from.
+    segment_index: usize,
+
+    /// The DE inside the current segment we're currently reading from.
+    element_index: usize,
+
+    /// The component inside the current DE we're currently reading from.
+    component_index: usize,
+
+    /// How many DEGs deep we currently are.
+    /// DEGs nested inside of DEGs don't have delimiters of their own so their components
+    /// simply continue in the surrounding DE.
+    deg_depth: usize,
+
+    /// Stack of the kinds of structs we're currently inside of.
+    struct_stack: Vec<StructKind>,
+}
+
+impl<'a> Deserializer<'a> {
+    pub fn new(segments: &'a [RawSegment]) -> Self {
+        Deserializer {
+            segments,
+            segment_index: 0,
+            element_index: 0,
+            component_index: 0,
+            deg_depth: 0,
+            struct_stack: vec![],
+        }
+    }
+
+    fn inside_message(&self) -> bool {
+        self.struct_stack.last() == Some(&StructKind::Message)
+    }
+
+    fn current_element(&self) -> Option<&'a Vec<Value>> {
+        self.segments
+            .get(self.segment_index)?
+            .elements
+            .get(self.element_index)
+    }
+
+    fn peek_value(&self) -> Option<&'a Value> {
+        let component_index = if self.deg_depth > 0 {
+            self.component_index
+        } else {
+            0
+        };
+        self.current_element()?.get(component_index)
+    }
+
+    /// Take the next value. Outside of a DEG this consumes a whole DE, inside of a DEG it
+    /// consumes a single component.
+    fn next_value(&mut self) -> Option<&'a Value> {
+        let value = self.peek_value();
+        if self.deg_depth > 0 {
+            self.component_index += 1;
+        } else {
+            self.element_index += 1;
+        }
+        value
+    }
+
+    /// Take the next value as text. Values that were left out on the wire are empty.
+    fn next_text(&mut self) -> Result<&'a str> {
+        match self.next_value() {
+            Some(Value::Text(text)) => Ok(text),
+            Some(Value::Binary(_)) => Err(Error("Expected text, found binary value".to_string())),
+            None => Ok(""),
+        }
+    }
+
+    fn parse_next<T>(&mut self) -> Result<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let text = self.next_text()?;
+        text.parse()
+            .map_err(|e| Error(format!("Invalid value {:?}: {}", text, e)))
+    }
+
+    fn parse_next_float<T>(&mut self) -> Result<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        // FinTS uses a decimal comma.
+        let text = self.next_text()?.replace(',', ".");
+        text.parse()
+            .map_err(|e| Error(format!("Invalid value {:?}: {}", text, e)))
+    }
+}
+
+/// Deserialize a message from its ISO 8859-15 encoded wire representation.
+pub fn from_bytes<T>(input: &[u8]) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    from_segments(&tokenize(input)?)
+}
+
+/// Deserialize a message, segment or DEG from its textual wire representation.
+pub fn from_str<T>(input: &str) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let (bytes, _, _) = ISO_8859_15.encode(input);
+    from_bytes(&bytes)
+}
+
+fn from_segments<T>(segments: &[RawSegment]) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    info!("Deserializing: {}", std::any::type_name::<T>());
+    let mut deserializer = Deserializer::new(segments);
+    T::deserialize(&mut deserializer)
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident, $parse:ident;)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value>
+            where
+                V: Visitor<'de>,
+            {
+                visitor.$visit(self.$parse()?)
+            }
+        )*
+    };
+}
+
+macro_rules! deserialize_unsupported {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            fn $method<V>(self, $($arg: $ty,)* _visitor: V) -> Result<V::Value>
+            where
+                V: Visitor<'de>,
+            {
+                Err(Error(format!("{} is not supported by FinTS", stringify!($method))))
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.next_value() {
+            Some(Value::Text(text)) => visitor.visit_borrowed_str(text),
+            Some(Value::Binary(data)) => visitor.visit_borrowed_bytes(data),
+            None => visitor.visit_unit(),
+        }
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.next_text()? {
+            "J" => visitor.visit_bool(true),
+            "N" => visitor.visit_bool(false),
+            other => Err(Error(format!("Invalid boolean {:?}", other))),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8, parse_next;
+        deserialize_i16 => visit_i16, parse_next;
+        deserialize_i32 => visit_i32, parse_next;
+        deserialize_i64 => visit_i64, parse_next;
+        deserialize_u8 => visit_u8, parse_next;
+        deserialize_u16 => visit_u16, parse_next;
+        deserialize_u32 => visit_u32, parse_next;
+        deserialize_u64 => visit_u64, parse_next;
+        deserialize_f32 => visit_f32, parse_next_float;
+        deserialize_f64 => visit_f64, parse_next_float;
+        deserialize_char => visit_char, parse_next;
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_borrowed_str(self.next_text()?)
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.next_value() {
+            Some(Value::Binary(data)) => visitor.visit_borrowed_bytes(data),
+            Some(Value::Text(text)) => visitor.visit_borrowed_bytes(text.as_bytes()),
+            None => visitor.visit_borrowed_bytes(&[]),
+        }
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.inside_message() {
+            // Only reached for repeated segments whose identifiers are checked by
+            // `Repetitions`. Optional segments are deserialized through `OptionalSegment`.
+            if self.segment_index < self.segments.len() {
+                return visitor.visit_some(self);
+            }
+            return visitor.visit_none();
+        }
+
+        let absent = if self.deg_depth > 0 {
+            // A single component, nested DEGs go through `OptionalDataElementGroup`.
+            self.peek_value().is_none_or(Value::is_empty)
+        } else {
+            // A DE or DEG is absent if all of its components are empty.
+            self.current_element()
+                .is_none_or(|components| components.iter().all(Value::is_empty))
+        };
+
+        if absent {
+            self.next_value();
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.next_value();
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.inside_message() {
+            // An optional segment, `name` is its identifier.
+            let is_present = self
+                .segments
+                .get(self.segment_index)
+                .is_some_and(|segment| segment.identifier() == name);
+            return if is_present {
+                visitor.visit_some(self)
+            } else {
+                visitor.visit_none()
+            };
+        }
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(Repetitions::new(self, None))
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(Repetitions::new(self, Some(len)))
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if name != OPTIONAL_DEG || self.deg_depth == 0 {
+            return Err(Error(
+                "deserialize_tuple_struct is not supported by FinTS".to_string(),
+            ));
+        }
+
+        // An optional DEG nested in another DEG, `len` is its number of components.
+        let absent = self.current_element().is_none_or(|components| {
+            components
+                .iter()
+                .skip(self.component_index)
+                .take(len)
+                .all(Value::is_empty)
+        });
+        if absent {
+            self.component_index += len;
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    deserialize_unsupported! {
+        deserialize_map();
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let kind = StructKind::from_name(name).ok_or_else(|| {
+            Error(format!(
+                "Can't deserialize struct {}, derive Message, Segment or DataElementGroup",
+                name
+            ))
+        })?;
+        self.struct_stack.push(kind);
+        let value = match kind {
+            StructKind::Message => visitor.visit_seq(Fields::new(self, fields.len())),
+            StructKind::Segment => {
+                if self.segment_index >= self.segments.len() {
+                    return Err(Error("Missing segment".to_string()));
+                }
+                self.element_index = 0;
+                self.component_index = 0;
+                self.deg_depth = 0;
+                let value = visitor.visit_seq(Fields::new(self, fields.len()));
+                self.segment_index += 1;
+                value
+            }
+            StructKind::DataElementGroup => {
+                if self.deg_depth == 0 {
+                    self.component_index = 0;
+                }
+                self.deg_depth += 1;
+                let value = visitor.visit_seq(Fields::new(self, fields.len()));
+                self.deg_depth -= 1;
+                if self.deg_depth == 0 {
+                    self.element_index += 1;
+                    self.component_index = 0;
+                }
+                value
+            }
+        };
+        self.struct_stack.pop();
+        value
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let variant: &str = self.next_text()?;
+        visitor.visit_enum(variant.into_deserializer())
+    }
+
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+}
+
+/// Hands out the fields of a struct one after another.
+struct Fields<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    remaining: usize,
+}
+
+impl<'a, 'de> Fields<'a, 'de> {
+    fn new(de: &'a mut Deserializer<'de>, remaining: usize) -> Self {
+        Fields { de, remaining }
+    }
+}
+
+impl<'de> SeqAccess<'de> for Fields<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+/// Hands out repetitions of a DE, DEG or segment until there are none left or `max` is reached.
+struct Repetitions<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    max: Option<usize>,
+    count: usize,
+
+    /// Identifier of the first repeated segment if we're repeating segments.
+    identifier: Option<&'de str>,
+}
+
+impl<'a, 'de> Repetitions<'a, 'de> {
+    fn new(de: &'a mut Deserializer<'de>, max: Option<usize>) -> Self {
+        Repetitions {
+            de,
+            max,
+            count: 0,
+            identifier: None,
+        }
+    }
+
+    /// Whether there's anything left at the current level that could be another repetition.
+    fn has_next(&self) -> bool {
+        let de = &self.de;
+        let segment = match de.segments.get(de.segment_index) {
+            Some(segment) => segment,
+            None => return false,
+        };
+        if de.inside_message() {
+            // Repeated segments have to directly follow each other.
+            self.identifier
+                .is_none_or(|identifier| segment.identifier() == identifier)
+        } else if de.deg_depth > 0 {
+            de.current_element()
+                .is_some_and(|components| de.component_index < components.len())
+        } else {
+            de.element_index < segment.elements.len()
+        }
+    }
+}
+
+impl<'de> SeqAccess<'de> for Repetitions<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.max.is_some_and(|max| self.count >= max) || !self.has_next() {
+            return Ok(None);
+        }
+        if self.de.inside_message() && self.identifier.is_none() {
+            self.identifier = Some(self.de.segments[self.de.segment_index].identifier());
+        }
+        self.count += 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+}
+
+/// Deserialize a list of DEs or DEGs that may be repeated at most `max` times.
+///
+/// This is the counterpart to [`crate::se::Repeated`]. Empty repetitions are skipped.
+pub struct Repeated<T> {
+    max: usize,
+    marker: PhantomData<T>,
+}
+
+impl<T> Repeated<T> {
+    pub fn new(max: usize) -> Self {
+        Repeated {
+            max,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'de, T> DeserializeSeed<'de> for Repeated<T>
+where
+    T: Deserialize<'de>,
+{
+    type Value = Vec<T>;
+
+    fn deserialize<D>(self, deserializer: D) -> std::result::Result<Vec<T>, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct RepeatedVisitor<T>(PhantomData<T>);
+
+        impl<'de, T> Visitor<'de> for RepeatedVisitor<T>
+        where
+            T: Deserialize<'de>,
+        {
+            type Value = Vec<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("repeated elements")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> std::result::Result<Vec<T>, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let mut values = vec![];
+                while let Some(value) = seq.next_element::<Option<T>>()? {
+                    values.extend(value);
+                }
+                Ok(values)
+            }
+        }
+
+        deserializer.deserialize_tuple(self.max, RepeatedVisitor(PhantomData))
+    }
+}
+
+/// Visitor for the optional segments and DEGs.
+struct OptionVisitor<T>(PhantomData<T>);
+
+impl<'de, T> Visitor<'de> for OptionVisitor<T>
+where
+    T: Deserialize<'de>,
+{
+    type Value = Option<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("option")
+    }
+
+    fn visit_none<E>(self) -> std::result::Result<Option<T>, E>
+    where
+        E: de::Error,
+    {
+        Ok(None)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> std::result::Result<Option<T>, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        T::deserialize(deserializer).map(Some)
+    }
+}
+
+/// Name passed to `deserialize_tuple_struct` by `OptionalDataElementGroup`.
+const OPTIONAL_DEG: &str = "$fints::OptionalDataElementGroup";
+
+/// Deserialize an optional DEG nested in another DEG. Like a DEG on its own it's only absent if
+/// all of its components are empty.
+pub struct OptionalDataElementGroup<T> {
+    marker: PhantomData<T>,
+}
+
+impl<T> OptionalDataElementGroup<T> {
+    pub fn new() -> Self {
+        OptionalDataElementGroup {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Default for OptionalDataElementGroup<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'de, T> DeserializeSeed<'de> for OptionalDataElementGroup<T>
+where
+    T: Deserialize<'de> + DataElementGroup,
+{
+    type Value = Option<T>;
+
+    fn deserialize<D>(self, deserializer: D) -> std::result::Result<Option<T>, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_tuple_struct(OPTIONAL_DEG, T::WIDTH, OptionVisitor(PhantomData))
+    }
+}
+
+/// Deserialize an optional segment of a message. The segment is only present if the next
+/// segment of the message has its identifier.
+pub struct OptionalSegment<T> {
+    marker: PhantomData<T>,
+}
+
+impl<T> OptionalSegment<T> {
+    pub fn new() -> Self {
+        OptionalSegment {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Default for OptionalSegment<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'de, T> DeserializeSeed<'de> for OptionalSegment<T>
+where
+    T: Deserialize<'de> + Segment,
+{
+    type Value = Option<T>;
+
+    fn deserialize<D>(self, deserializer: D) -> std::result::Result<Option<T>, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        // The identifier is passed as the name so the deserializer can check whether the
+        // segment is present.
+        deserializer.deserialize_newtype_struct(T::ID, OptionVisitor(PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_types::*;
+    use crate::segments::*;
+    use fints_derive::{Message, Segment};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_tokenize_delimiters() {
+        let message = RawMessage::from_bytes(b"HNHBS:5:1+1'HKEND:3:1+abc:def+'").unwrap();
+        assert_eq!(message.segments.len(), 2);
+        assert_eq!(message.segments[0].identifier(), "HNHBS");
+        assert_eq!(message.segments[0].segment_no(), Some(5));
+        assert_eq!(message.segments[0].version(), Some(1));
+        assert_eq!(
+            message.segments[1].elements[1],
+            vec![
+                Value::Text("abc".to_string()),
+                Value::Text("def".to_string())
+            ]
+        );
+        assert_eq!(
+            message.segments[1].elements[2],
+            vec![Value::Text("".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_escaping() {
+        let message =
+            RawMessage::from_bytes(b"HIRMS:4:2:3+3920::PIN ?+ TAN?: ?'ok?'?@??'").unwrap();
+        assert_eq!(
+            message.segments[0].elements[1][2],
+            Value::Text("PIN + TAN: 'ok'@?".to_string())
+        );
+    }
+
+    #[test]
+    fn test_tokenize_binary() {
+        let message = RawMessage::from_bytes(b"HNVSD:999:1+@5@a+:'\xff+x'").unwrap();
+        assert_eq!(message.segments.len(), 1);
+        assert_eq!(
+            message.segments[0].elements[1],
+            vec![Value::Binary(b"a+:'\xff".to_vec())]
+        );
+        assert_eq!(
+            message.segments[0].elements[2],
+            vec![Value::Text("x".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_iso_8859_15() {
+        let message = RawMessage::from_bytes(b"HIUPD:1:6+M\xfcller'").unwrap();
+        assert_eq!(
+            message.segments[0].elements[1][0],
+            Value::Text("Müller".to_string())
+        );
+    }
+
+    #[test]
+    fn test_tokenize_errors() {
+        assert!(RawMessage::from_bytes(b"HNHBS:5:1+1?").is_err());
+        assert!(RawMessage::from_bytes(b"HNVSD:999:1+@10@abc'").is_err());
+        assert!(RawMessage::from_bytes(b"HNVSD:999:1+@2@abc'").is_err());
+        assert!(RawMessage::from_bytes(b"HNVSD:999:1+@18446744073709551615@abc'").is_err());
+    }
+
+    #[test]
+    fn test_deserialize_deg() {
+        let head: DEG_SegmentHead = from_str("HIRMS:4:2:3").unwrap();
+        assert_eq!(head.identifier, "HIRMS");
+        assert_eq!(head.segment_no, 4);
+        assert_eq!(head.version, 2);
+        assert_eq!(head.reference_seg, Some(3));
+
+        let head: DEG_SegmentHead = from_str("HNHBS:5:1").unwrap();
+        assert_eq!(head.reference_seg, None);
+    }
+
+    #[test]
+    fn test_deserialize_message_head() {
+        let head: Seg_HNHBK_MessageHead =
+            from_str("HNHBK:1:3+000000000181+300+817248153426=913620211115BF1U=+1'").unwrap();
+        assert_eq!(head.segment_head.identifier, "HNHBK");
+        assert_eq!(head.message_size, 181);
+        assert_eq!(head.hbci_version, 300);
+        assert_eq!(head.dialog_id, "817248153426=913620211115BF1U=");
+        assert_eq!(head.message_no, 1);
+        assert!(head.reference_msg.is_none());
+    }
+
+    #[test]
+    fn test_deserialize_nested_degs() {
+        let identification: Seg_HKIDN_Identification =
+            from_str("HKIDN:3:2+280:12345678+test1+0+1'").unwrap();
+        assert_eq!(identification.institute_identifier.country_code, "280");
+        assert_eq!(identification.institute_identifier.bank_code, 12345678);
+        assert_eq!(identification.customer_id, "test1");
+        assert_eq!(identification.customer_system_id, "0");
+
+        let signature_end: Seg_HNSHA_SignatureEnd = from_str("HNSHA:6:2+1234567++1234'").unwrap();
+        assert_eq!(signature_end.security_reference, "1234567");
+        assert!(signature_end.validation_result.is_none());
+        let signature = signature_end.user_defined_signature.unwrap();
+        assert_eq!(signature.PIN, "1234");
+        assert!(signature.TAN.is_none());
+    }
+
+    #[test]
+    fn test_deserialize_optional_nested_deg() {
+        let limit: DEG_AccountLimit = from_str("E:::5").unwrap();
+        assert!(limit.amount.is_none());
+        assert_eq!(limit.limit_days, Some(5));
+
+        let limit: DEG_AccountLimit = from_str("E:100,5:EUR:5").unwrap();
+        assert_eq!(limit.amount.unwrap().currency, "EUR");
+        assert_eq!(limit.limit_days, Some(5));
+
+        // Only the first component of the nested DEG is empty
+        let connection: DEG_InternationalAccountConnection =
+            from_str("DE02120300000000202051:::::12030000").unwrap();
+        let institute = connection.institute_identifier.unwrap();
+        assert_eq!(institute.country_code, "");
+        assert_eq!(institute.bank_code, 12030000);
+
+        let connection: DEG_InternationalAccountConnection =
+            from_str("DE02120300000000202051::::::").unwrap();
+        assert!(connection.institute_identifier.is_none());
+    }
+
+    #[test]
+    fn test_deserialize_raw_segment() {
+        let message =
+            RawMessage::from_bytes(b"HNHBK:1:3+000000000042+300+abc+2+abc:1'HNHBS:2:1+2'").unwrap();
+        let end: Seg_HNHBS_MessageEnd = message.find("HNHBS").unwrap().deserialize().unwrap();
+        assert_eq!(end.segment_head.segment_no, 2);
+        assert_eq!(end.message_no, 2);
+        assert!(message.find("HIRMG").is_none());
+        assert_eq!(message.find_all("HNHBK").count(), 1);
+    }
+
+    #[test]
+    fn test_deserialize_raw_segment_identifier() {
+        let message = RawMessage::from_bytes(b"HNHBS:2:1+2'").unwrap();
+        let result: Result<Seg_HKEND_DialogEnd> = message.segments[0].deserialize();
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Expected segment HKEND, found HNHBS"
+        );
+    }
+
+    #[test]
+    fn test_deserialize_optional_segment() {
+        #[derive(Debug, Message)]
+        struct Optional {
+            message_head: Seg_HNHBK_MessageHead,
+            pubkey: Option<Seg_HKISA_RequestForPubkey>,
+            dialog_end: Option<Seg_HKEND_DialogEnd>,
+            message_end: Seg_HNHBS_MessageEnd,
+        }
+
+        let message: Optional =
+            from_str("HNHBK:1:3+000000000042+300+abc+2'HKEND:2:1+abc'HNHBS:3:1+2'").unwrap();
+        assert!(message.pubkey.is_none());
+        assert_eq!(message.dialog_end.unwrap().dialog_id, "abc");
+        assert_eq!(message.message_end.message_no, 2);
+
+        let message: Optional = from_str("HNHBK:1:3+000000000042+300+abc+2'HNHBS:2:1+2'").unwrap();
+        assert!(message.pubkey.is_none());
+        assert!(message.dialog_end.is_none());
+        assert_eq!(message.message_end.segment_head.segment_no, 2);
+    }
+
+    #[test]
+    fn test_deserialize_invalid_number() {
+        let result: Result<DEG_SegmentHead> = from_str("HNHBS:x:1");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_deserialize_repeated_elements() {
+        #[allow(non_camel_case_types)]
+        #[derive(Debug, Segment)]
+        #[fints(id = "HIXYZ", version = 1)]
+        struct Seg_HIXYZ_Repeated {
+            segment_head: DEG_SegmentHead,
+            #[fints(max = 2)]
+            bounded: Vec<String>,
+            single: String,
+            rest: Vec<Option<u16>>,
+        }
+
+        let repeated: Seg_HIXYZ_Repeated = from_str("HIXYZ:1:1+a+b+c+1++3'").unwrap();
+        assert_eq!(repeated.bounded, vec!["a", "b"]);
+        assert_eq!(repeated.single, "c");
+        assert_eq!(repeated.rest, vec![Some(1), None, Some(3)]);
+
+        // Unused repetitions are left empty and skipped.
+        let repeated: Seg_HIXYZ_Repeated = from_str("HIXYZ:1:1+a++c'").unwrap();
+        assert_eq!(repeated.bounded, vec!["a"]);
+        assert_eq!(repeated.single, "c");
+        assert!(repeated.rest.is_empty());
+    }
+}
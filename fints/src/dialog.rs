@@ -1,6 +1,124 @@
+use crate::data_types::{
+    DEG_Feedback, ReturnCode, ReturnCodeCategory, SecurityFunction, TanProcess,
+};
+use crate::de::{self, RawMessage};
+use crate::error::Error;
 use crate::messages::*;
-use base64;
+use crate::parameters::{BankParameters, UserParameters};
+use crate::segments::*;
+use crate::state::ClientState;
+use log::{info, warn};
+use serde::de::Error as _;
+use std::fmt;
 
+/// Feedback on a whole message (HIRMG) or on one of its segments (HIRMS).
+#[derive(Debug)]
+pub struct Feedback {
+    /// The segment of our message this feedback refers to or `None` if it refers to the whole
+    /// message.
+    pub reference_seg: Option<u16>,
+
+    pub code: ReturnCode,
+
+    /// The DE of the referenced segment this feedback refers to.
+    pub reference_element: Option<String>,
+
+    pub text: String,
+
+    pub parameters: Vec<String>,
+}
+
+impl Feedback {
+    fn new(reference_seg: Option<u16>, feedback: DEG_Feedback) -> Feedback {
+        Feedback {
+            reference_seg,
+            code: feedback.code,
+            reference_element: feedback.reference_element,
+            text: feedback.text,
+            parameters: feedback.parameters,
+        }
+    }
+}
+
+impl fmt::Display for Feedback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.code, self.text)?;
+        if let Some(reference_seg) = self.reference_seg {
+            write!(f, " (segment {})", reference_seg)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collect the feedback from all HIRMG and HIRMS segments of a response.
+pub fn collect_feedback(response: &RawMessage) -> Result<Vec<Feedback>, de::Error> {
+    let mut feedback = vec![];
+    for segment in &response.segments {
+        match segment.identifier() {
+            Seg_HIRMG_MessageFeedback::ID => {
+                let message_feedback: Seg_HIRMG_MessageFeedback = segment.deserialize()?;
+                feedback.extend(
+                    message_feedback
+                        .feedback
+                        .into_iter()
+                        .map(|f| Feedback::new(None, f)),
+                );
+            }
+            Seg_HIRMS_SegmentFeedback::ID => {
+                let segment_feedback: Seg_HIRMS_SegmentFeedback = segment.deserialize()?;
+                let reference_seg = segment_feedback.segment_head.reference_seg;
+                feedback.extend(
+                    segment_feedback
+                        .feedback
+                        .into_iter()
+                        .map(|f| Feedback::new(reference_seg, f)),
+                );
+            }
+            _ => {}
+        }
+    }
+    Ok(feedback)
+}
+
+/// The touchdown point (Aufsetzpunkt) to request the next page of a job's results with, if the
+/// bank announced more data with 3040.
+pub fn touchdown(feedback: &[Feedback]) -> Option<&str> {
+    feedback
+        .iter()
+        .find(|f| f.code == ReturnCode::MoreDataAvailable)
+        .and_then(|f| f.parameters.first())
+        .map(String::as_str)
+}
+
+/// Where a dialog is in its lifecycle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DialogState {
+    /// Neither synchronization nor dialog initialization has been answered yet.
+    New,
+
+    /// The dialog was initialized and jobs can be sent.
+    Open,
+
+    /// The dialog end was sent or the bank ended the dialog.
+    Ended,
+}
+
+impl fmt::Display for DialogState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DialogState::New => write!(f, "not initialized"),
+            DialogState::Open => write!(f, "open"),
+            DialogState::Ended => write!(f, "ended"),
+        }
+    }
+}
+
+/// A dialog with the bank.
+///
+/// A dialog is started either by a synchronization or by a dialog initialization, followed by
+/// any number of jobs and finally ended with a dialog end. The dialog builds the messages for
+/// each of these steps and learns the dialog id and customer system id from the responses. It
+/// doesn't send anything itself.
 #[derive(Debug)]
 pub struct Dialog {
     // TODO: Dedup this somehow.
@@ -13,18 +131,24 @@ pub struct Dialog {
     /// Pin or password.
     pub pin: String,
 
-    /// The `customer_system_id` starts at `0` (as per C.8) and will be assigned by the bank on
-    /// first sync.
-    pub customer_system_id: String,
+    /// Customer system id, BPD and UPD. These are updated from the responses and should be
+    /// kept for later dialogs.
+    pub client_state: ClientState,
 
     /// The `message_no` starts at `1` and will be incremented for every message sent.
     pub message_no: u16,
 
-    // pub dialogId: String,
+    /// The `dialog_id` starts at `0` and will be assigned by the bank when the dialog is
+    /// initialized.
+    pub dialog_id: String,
+
     /// List of TAN methods as returned by the bank on first sync.
     pub tan_methods: Vec<String>,
-    // pub hisalsVersion: u32,
-    // pub hikazsVerson: u32,
+    state: DialogState,
+
+    bank_parameters: Option<BankParameters>,
+
+    user_parameters: Option<UserParameters>,
 }
 
 impl Dialog {
@@ -34,32 +158,435 @@ impl Dialog {
             bank_code,
             username: username.to_string(),
             pin: pin.to_string(),
-            customer_system_id: "0".to_string(),
+            client_state: ClientState::default(),
             message_no: 1,
-            // dialogId: "0".to_string(),
+            dialog_id: "0".to_string(),
             tan_methods: vec![],
-            // hisalsVersion: 6,
-            // hikazsVerson: 6,
+            state: DialogState::New,
+            bank_parameters: None,
+            user_parameters: None,
+        }
+    }
+
+    pub fn state(&self) -> DialogState {
+        self.state
+    }
+
+    /// Bank parameters, available once the dialog was initialized.
+    pub fn bank_parameters(&self) -> Option<&BankParameters> {
+        self.bank_parameters.as_ref()
+    }
+
+    /// Highest version of a job such as `HKSAL` supported by both the bank and us.
+    pub fn job_version(&self, job: &str, our_versions: &[u16]) -> Result<u16, Error> {
+        self.bank_parameters
+            .as_ref()
+            .and_then(|p| p.highest_common_version(job, our_versions))
+            .ok_or_else(|| Error::UnsupportedJob(job.to_string()))
+    }
+
+    /// User parameters, available once the dialog was initialized.
+    pub fn user_parameters(&self) -> Option<&UserParameters> {
+        self.user_parameters.as_ref()
+    }
+
+    /// Synchronization message requesting a new customer system id.
+    ///
+    /// This starts a dialog just like [`Dialog::get_init_message`] does.
+    pub fn get_sync_message(&mut self) -> Result<Vec<u8>, Error> {
+        self.expect_state(DialogState::New)?;
+        let message = MessageBuilder::new(self)
+            .segment(new_identification(
+                self.bank_code,
+                &self.username,
+                &self.client_state.customer_system_id,
+            ))
+            .segment(new_processing_preparation(
+                self.client_state.bpd.version,
+                self.client_state.upd.version,
+            ))
+            .segment(new_synchronization())
+            .build()?;
+        self.message_no += 1;
+        Ok(message)
+    }
+
+    /// Dialog initialization message.
+    pub fn get_init_message(&mut self) -> Result<Vec<u8>, Error> {
+        self.expect_state(DialogState::New)?;
+        let message = MessageBuilder::new(self)
+            .segment(new_identification(
+                self.bank_code,
+                &self.username,
+                &self.client_state.customer_system_id,
+            ))
+            .segment(new_processing_preparation(
+                self.client_state.bpd.version,
+                self.client_state.upd.version,
+            ));
+        let message = if self.uses_two_step_tan() {
+            message.segment(new_tan_submission(
+                TanProcess::JobSubmission,
+                Some(Seg_HKIDN_Identification::ID),
+                None,
+            ))
+        } else {
+            message
         }
+        .build()?;
+        self.message_no += 1;
+        Ok(message)
+    }
+
+    /// Message containing a job in an initialized dialog.
+    pub fn get_job_message<S>(&mut self, segment: S) -> Result<Vec<u8>, Error>
+    where
+        S: BusinessSegment,
+    {
+        self.expect_state(DialogState::Open)?;
+        let message = MessageBuilder::new(self).segment(segment).build()?;
+        self.message_no += 1;
+        Ok(message)
     }
 
-    pub fn get_sync_message(&self) -> String {
-        let dialog_sync_message = Msg_DialogSync::new(self.bank_code, &self.username, &self.pin, &self.customer_system_id, self.message_no);
-        dialog_sync_message.prepare_message_for_sending()
+    /// Message containing a job that may need a TAN. If [`Dialog::is_tan_required`] for the
+    /// job, HKTAN is added to start the TAN process for it.
+    pub fn get_tan_job_message<S>(&mut self, segment: S) -> Result<Vec<u8>, Error>
+    where
+        S: BusinessSegment + Segment,
+    {
+        self.expect_state(DialogState::Open)?;
+        let message = MessageBuilder::new(self).segment(segment);
+        let message = if self.is_tan_required(S::ID) {
+            message.segment(new_tan_submission(
+                TanProcess::JobSubmission,
+                Some(S::ID),
+                None,
+            ))
+        } else {
+            message
+        }
+        .build()?;
+        self.message_no += 1;
+        Ok(message)
+    }
 
-        // TODO Send request
-        // this.systemId = response.systemId;
-        // this.dialogId = response.dialogId;
-        // this.hisalsVersion = response.segmentMaxVersion(HISALS);
-        // this.hikazsVersion = response.segmentMaxVersion(HIKAZS);
-        // this.tanMethods = response.supportedTanMethods;
-        // await this.end();
+    /// Message submitting the TAN for the job the bank returned `job_reference` for in HITAN.
+    pub fn get_tan_message(&mut self, job_reference: &str, tan: &str) -> Result<Vec<u8>, Error> {
+        self.expect_state(DialogState::Open)?;
+        let message = MessageBuilder::new(self)
+            .segment(new_tan_submission(
+                TanProcess::TanSubmission,
+                None,
+                Some(job_reference),
+            ))
+            .tan(tan)
+            .build()?;
+        self.message_no += 1;
+        Ok(message)
     }
 
-    pub fn init(&mut self) {
-        // let dialog_init_message = DialogInitMessage::new(self.bank_code, &self.username, &self.pin, &self.customer_system_id, self.message_no);
-        // // TODO Send request
-        // let serialized = to_string(&dialog_sync_message).unwrap();
-        // println!("{}", serialized);
+    /// Dialog end message. The dialog can't be used anymore afterwards.
+    pub fn get_end_message(&mut self) -> Result<Vec<u8>, Error> {
+        self.expect_state(DialogState::Open)?;
+        let message = MessageBuilder::new(self)
+            .segment(new_dialog_end(&self.dialog_id))
+            .build()?;
+        self.message_no += 1;
+        self.state = DialogState::Ended;
+        Ok(message)
+    }
+
+    fn uses_two_step_tan(&self) -> bool {
+        self.client_state.security_function() != SecurityFunction::SingleStepAuth
+    }
+
+    /// Whether a job such as `HKCCS` has to be sent with HKTAN: a two-step TAN method is used
+    /// and the bank requires a TAN for the job in HIPINS. Without BPD we assume it does.
+    pub fn is_tan_required(&self, job: &str) -> bool {
+        self.uses_two_step_tan()
+            && self
+                .bank_parameters
+                .as_ref()
+                .is_none_or(|bank_parameters| bank_parameters.is_tan_required(job))
+    }
+
+    fn expect_state(&self, expected: DialogState) -> Result<(), Error> {
+        if self.state == expected {
+            Ok(())
+        } else {
+            Err(Error::InvalidDialogState(self.state))
+        }
+    }
+
+    /// Process the response to the last message, failing on the first error the bank reported.
+    ///
+    /// The dialog id, customer system id and parameter data sent by the bank are stored for the
+    /// following messages.
+    pub fn process_response(&mut self, response: &RawMessage) -> Result<Vec<Feedback>, Error> {
+        if let Some(segment) = response.find(Seg_HNHBK_MessageHead::ID) {
+            let message_head: Seg_HNHBK_MessageHead = segment.deserialize()?;
+            if let Some(reference) = message_head.reference_msg {
+                let sent_message_no = self.message_no.checked_sub(1).ok_or_else(|| {
+                    Error::Parsing(de::Error::custom(format!(
+                        "Response refers to message {} but none was sent",
+                        reference.message_no
+                    )))
+                })?;
+                if reference.message_no != sent_message_no {
+                    return Err(Error::Parsing(de::Error::custom(format!(
+                        "Response refers to message {} instead of {}",
+                        reference.message_no, sent_message_no
+                    ))));
+                }
+            }
+            self.dialog_id = message_head.dialog_id;
+        }
+        if let Some(segment) = response.find(Seg_HISYN_SynchronizationResponse::ID) {
+            let sync_response: Seg_HISYN_SynchronizationResponse = segment.deserialize()?;
+            if let Some(customer_system_id) = sync_response.customer_system_id {
+                self.client_state.customer_system_id = customer_system_id;
+            }
+        }
+        self.client_state.update_parameter_data(response);
+        let bpd = &self.client_state.bpd;
+        let is_outdated = self.bank_parameters.as_ref().map(|p| p.version) != Some(bpd.version);
+        if !bpd.segments.is_empty() && is_outdated {
+            self.bank_parameters = Some(BankParameters::from_parameter_data(bpd)?);
+        }
+        let upd = &self.client_state.upd;
+        let is_outdated = self.user_parameters.as_ref().map(|p| p.version) != Some(upd.version);
+        if !upd.segments.is_empty() && is_outdated {
+            self.user_parameters = Some(UserParameters::from_parameter_data(upd)?);
+        }
+
+        let feedback = collect_feedback(response)?;
+        for f in &feedback {
+            match f.code.category() {
+                ReturnCodeCategory::Success => info!("{}", f),
+                ReturnCodeCategory::Warning => warn!("{}", f),
+                ReturnCodeCategory::Error => {}
+            }
+            if f.code == ReturnCode::AllowedTanMethods {
+                self.tan_methods = f.parameters.clone();
+                self.client_state.select_tan_method(&self.tan_methods);
+            }
+        }
+        if feedback
+            .iter()
+            .any(|f| f.code == ReturnCode::DialogEnded || f.code == ReturnCode::DialogAborted)
+        {
+            self.state = DialogState::Ended;
+        }
+        if let Some(index) = feedback
+            .iter()
+            .position(|f| f.code.category() == ReturnCodeCategory::Error)
+        {
+            let mut feedback = feedback;
+            return Err(Error::from_feedback(feedback.swap_remove(index)));
+        }
+        if self.state == DialogState::New {
+            self.state = DialogState::Open;
+        }
+        Ok(feedback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_types::DEG_InternationalAccountConnection;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_collect_feedback() {
+        let response = RawMessage::from_bytes(
+            b"HNHBK:1:3+000000000200+300+abc+1+abc:1'\
+              HIRMG:2:2+0010::Nachricht entgegengenommen.+3060::Bitte beachten Sie die Hinweise.'\
+              HIRMS:3:2:4+3920::Zugelassene Verfahren.:910:911+3040:3:Weitere Daten:abc?:1'\
+              HNHBS:4:1+1'",
+        )
+        .unwrap();
+        let feedback = collect_feedback(&response).unwrap();
+        assert_eq!(feedback.len(), 4);
+
+        assert_eq!(feedback[0].code, ReturnCode::MessageReceived);
+        assert_eq!(feedback[0].reference_seg, None);
+        assert_eq!(feedback[0].text, "Nachricht entgegengenommen.");
+        assert_eq!(feedback[1].code.category(), ReturnCodeCategory::Warning);
+
+        assert_eq!(feedback[2].code, ReturnCode::AllowedTanMethods);
+        assert_eq!(feedback[2].reference_seg, Some(4));
+        assert_eq!(feedback[2].parameters, vec!["910", "911"]);
+
+        assert_eq!(feedback[3].code, ReturnCode::MoreDataAvailable);
+        assert_eq!(feedback[3].reference_element.as_deref(), Some("3"));
+        assert_eq!(feedback[3].parameters, vec!["abc:1"]);
+        assert_eq!(touchdown(&feedback), Some("abc:1"));
+        assert_eq!(touchdown(&feedback[..3]), None);
+
+        let mut dialog = Dialog::new(12345678, "test1", "1234");
+        dialog.get_init_message().unwrap();
+        assert_eq!(dialog.process_response(&response).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_process_response_error() {
+        let response = RawMessage::from_bytes(
+            b"HIRMG:2:2+3060::Bitte beachten Sie die Hinweise.'\
+              HIRMS:3:2:3+9942::PIN falsch.'",
+        )
+        .unwrap();
+        let mut dialog = Dialog::new(12345678, "test1", "1234");
+        match dialog.process_response(&response) {
+            Err(Error::Bank { code, text }) => {
+                assert_eq!(code, ReturnCode::InvalidPin);
+                assert_eq!(text, "PIN falsch.");
+            }
+            other => panic!("Expected rejection, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_dialog_lifecycle() {
+        let mut dialog = Dialog::new(12345678, "test1", "1234");
+        dialog.client_state.bpd.version = 12;
+        assert_eq!(dialog.state(), DialogState::New);
+
+        let message = decrypt(RawMessage::from_bytes(&dialog.get_sync_message().unwrap()).unwrap());
+        let message = message.unwrap();
+        let message_head: Seg_HNHBK_MessageHead = message.segments[0].deserialize().unwrap();
+        assert_eq!(message_head.dialog_id, "0");
+        assert_eq!(message_head.message_no, 1);
+        let processing_preparation: Seg_HKVVB_ProcessingPreparation =
+            message.segments[3].deserialize().unwrap();
+        assert_eq!(processing_preparation.bpd_version, 12);
+        assert_eq!(processing_preparation.upd_version, 0);
+        assert_eq!(dialog.message_no, 2);
+
+        let response = RawMessage::from_bytes(
+            b"HNHBK:1:3+000000000200+300+abc+1+0:1'\
+              HIRMG:2:2+0010::Nachricht entgegengenommen.'\
+              HISYN:3:4:5+sys123'\
+              HNHBS:4:1+1'",
+        )
+        .unwrap();
+        dialog.process_response(&response).unwrap();
+        assert_eq!(dialog.state(), DialogState::Open);
+        assert_eq!(dialog.dialog_id, "abc");
+        assert_eq!(dialog.client_state.customer_system_id, "sys123");
+
+        let message = decrypt(RawMessage::from_bytes(&dialog.get_end_message().unwrap()).unwrap());
+        let message = message.unwrap();
+        let message_head: Seg_HNHBK_MessageHead = message.segments[0].deserialize().unwrap();
+        assert_eq!(message_head.dialog_id, "abc");
+        assert_eq!(message_head.message_no, 2);
+        let dialog_end: Seg_HKEND_DialogEnd = message.segments[2].deserialize().unwrap();
+        assert_eq!(dialog_end.dialog_id, "abc");
+        assert_eq!(dialog.state(), DialogState::Ended);
+
+        match dialog.get_job_message(new_synchronization()) {
+            Err(Error::InvalidDialogState(DialogState::Ended)) => {}
+            other => panic!("Expected ended dialog, got {:?}", other),
+        }
+        assert!(dialog.get_init_message().is_err());
+        assert!(dialog.get_end_message().is_err());
+    }
+
+    #[test]
+    fn test_two_step_tan() {
+        let mut dialog = Dialog::new(12345678, "test1", "1234");
+        dialog.client_state.tan_method = Some("920".to_string());
+
+        let message = decrypt(RawMessage::from_bytes(&dialog.get_init_message().unwrap()).unwrap());
+        let message = message.unwrap();
+        let signature_head: Seg_HNSHK_SignatureHead =
+            message.find("HNSHK").unwrap().deserialize().unwrap();
+        assert_eq!(
+            signature_head.security_function,
+            SecurityFunction::TwoStepAuth(920)
+        );
+        let tan_submission: Seg_HKTAN_TwoStepTanSubmission =
+            message.find("HKTAN").unwrap().deserialize().unwrap();
+        assert_eq!(tan_submission.segment_identifier.as_deref(), Some("HKIDN"));
+
+        let response = RawMessage::from_bytes(
+            b"HNHBK:1:3+000000000200+300+abc+1+0:1'\
+              HIRMS:2:2:5+3920::Zugelassene Verfahren.:942:999'\
+              HIBPA:3:3:5+12+280:12345678+Testbank+3+1:2+220:300+10000'\
+              HIPINS:4:1:5+1+1+0+5:20:6:Benutzerkennung::HKSPA:J:HKSAL:N'",
+        )
+        .unwrap();
+        dialog.process_response(&response).unwrap();
+        assert_eq!(dialog.client_state.tan_method.as_deref(), Some("942"));
+        assert!(dialog.is_tan_required("HKSPA"));
+        assert!(!dialog.is_tan_required("HKSAL"));
+
+        // No TAN needed according to HIPINS
+        let message = dialog
+            .get_tan_job_message(Seg_HKSAL_Balance7 {
+                segment_head: Seg_HKSAL_Balance7::new_segment_head(0),
+                account: DEG_InternationalAccountConnection {
+                    iban: Some("DE00123456780001234567".to_string()),
+                    bic: None,
+                    account_number: None,
+                    sub_account: None,
+                    institute_identifier: None,
+                },
+                all_accounts: false,
+                max_entries: None,
+                touchdown: None,
+            })
+            .unwrap();
+        let message = decrypt(RawMessage::from_bytes(&message).unwrap()).unwrap();
+        assert!(message.find("HKSAL").is_some());
+        assert!(message.find("HKTAN").is_none());
+
+        let message = dialog.get_tan_job_message(new_sepa_account_info()).unwrap();
+        let message = decrypt(RawMessage::from_bytes(&message).unwrap()).unwrap();
+        let identifiers: Vec<_> = message.segments.iter().map(|s| s.identifier()).collect();
+        assert_eq!(
+            identifiers,
+            vec!["HNHBK", "HNSHK", "HKSPA", "HKTAN", "HNSHA", "HNHBS"]
+        );
+        let tan_submission: Seg_HKTAN_TwoStepTanSubmission =
+            message.find("HKTAN").unwrap().deserialize().unwrap();
+        assert_eq!(tan_submission.segment_identifier.as_deref(), Some("HKSPA"));
+
+        let message = dialog.get_tan_message("ref123", "123456").unwrap();
+        let message = decrypt(RawMessage::from_bytes(&message).unwrap()).unwrap();
+        let tan_submission: Seg_HKTAN_TwoStepTanSubmission =
+            message.find("HKTAN").unwrap().deserialize().unwrap();
+        assert_eq!(tan_submission.job_reference.as_deref(), Some("ref123"));
+        assert_eq!(tan_submission.further_tan_follows, Some(false));
+        let signature_end: Seg_HNSHA_SignatureEnd =
+            message.find("HNSHA").unwrap().deserialize().unwrap();
+        let signature = signature_end.user_defined_signature.unwrap();
+        assert_eq!(signature.TAN.as_deref(), Some("123456"));
+    }
+
+    #[test]
+    fn test_process_response_wrong_reference() {
+        let mut dialog = Dialog::new(12345678, "test1", "1234");
+        dialog.get_init_message().unwrap();
+        let response =
+            RawMessage::from_bytes(b"HNHBK:1:3+000000000200+300+abc+1+abc:2'HNHBS:2:1+1'").unwrap();
+        match dialog.process_response(&response) {
+            Err(Error::Parsing(err)) => {
+                assert_eq!(err.to_string(), "Response refers to message 2 instead of 1")
+            }
+            other => panic!("Expected parsing error, got {:?}", other),
+        }
+
+        dialog.message_no = 0;
+        match dialog.process_response(&response) {
+            Err(Error::Parsing(err)) => {
+                assert_eq!(
+                    err.to_string(),
+                    "Response refers to message 2 but none was sent"
+                )
+            }
+            other => panic!("Expected parsing error, got {:?}", other),
+        }
     }
 }
@@ -0,0 +1,156 @@
+use crate::data_types::ReturnCode;
+use crate::dialog::{DialogState, Feedback};
+use crate::{de, se};
+use std::fmt;
+
+/// Everything that can go wrong when talking to a bank.
+#[derive(Debug)]
+pub enum Error {
+    /// The bank couldn't be reached or answered with an error, e.g. an HTTP error of
+    /// [`HttpTransport`](crate::HttpTransport) or whatever another [`Transport`](crate::Transport)
+    /// reports.
+    Transport(Box<dyn std::error::Error + Send + Sync>),
+
+    /// The response wasn't valid base64.
+    Encoding(base64::DecodeError),
+
+    /// A message couldn't be serialized.
+    Serialization(se::Error),
+
+    /// A response couldn't be parsed.
+    Parsing(de::Error),
+
+    /// The bank rejected the message or one of its segments with a 9xxx return code.
+    Bank { code: ReturnCode, text: String },
+
+    /// The job has to be confirmed using a TAN.
+    TanRequired { text: String },
+
+    /// The bank wants the job approved in another channel such as its banking app (3955),
+    /// which isn't supported. The job is not carried out.
+    DecoupledApprovalRequired { text: String },
+
+    /// Access is blocked, usually after entering a wrong PIN too often.
+    PinBlocked { text: String },
+
+    /// The dialog can't be used for this in its current state, e.g. because it already ended.
+    InvalidDialogState(DialogState),
+
+    /// The bank doesn't support the job, e.g. `HKSAL`, in any version we know.
+    UnsupportedJob(String),
+
+    /// The job exceeds a limit the bank announced in its BPD, e.g. the number of transfers in
+    /// a batch.
+    LimitExceeded(String),
+}
+
+impl Error {
+    /// Turn feedback with an error return code into the matching error.
+    pub fn from_feedback(feedback: Feedback) -> Error {
+        match feedback.code {
+            ReturnCode::AccessBlocked => Error::PinBlocked {
+                text: feedback.text,
+            },
+            code => Error::Bank {
+                code,
+                text: feedback.text,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Transport(err) => write!(f, "Transport error: {}", err),
+            Error::Encoding(err) => write!(f, "Invalid encoding: {}", err),
+            Error::Serialization(err) => write!(f, "Serialization error: {}", err),
+            Error::Parsing(err) => write!(f, "Invalid response: {}", err),
+            Error::Bank { code, text } => write!(f, "Rejected by bank: {} {}", code, text),
+            Error::TanRequired { text } => write!(f, "TAN required: {}", text),
+            Error::DecoupledApprovalRequired { text } => {
+                write!(f, "Approval in another channel required: {}", text)
+            }
+            Error::PinBlocked { text } => write!(f, "PIN blocked: {}", text),
+            Error::InvalidDialogState(state) => write!(f, "Dialog is {}", state),
+            Error::UnsupportedJob(job) => write!(f, "Job {} is not supported by the bank", job),
+            Error::LimitExceeded(limit) => write!(f, "Bank limit exceeded: {}", limit),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Transport(err) => Some(err.as_ref()),
+            Error::Encoding(err) => Some(err),
+            Error::Serialization(err) => Some(err),
+            Error::Parsing(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Transport(Box::new(err))
+    }
+}
+
+impl From<base64::DecodeError> for Error {
+    fn from(err: base64::DecodeError) -> Self {
+        Error::Encoding(err)
+    }
+}
+
+impl From<se::Error> for Error {
+    fn from(err: se::Error) -> Self {
+        Error::Serialization(err)
+    }
+}
+
+impl From<de::Error> for Error {
+    fn from(err: de::Error) -> Self {
+        Error::Parsing(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn feedback(code: u16, text: &str) -> Feedback {
+        Feedback {
+            reference_seg: Some(3),
+            code: ReturnCode::from_code(code),
+            reference_element: None,
+            text: text.to_string(),
+            parameters: vec![],
+        }
+    }
+
+    #[test]
+    fn test_error_from_feedback() {
+        match Error::from_feedback(feedback(9931, "Zugang gesperrt.")) {
+            Error::PinBlocked { text } => assert_eq!(text, "Zugang gesperrt."),
+            other => panic!("Expected blocked PIN, got {:?}", other),
+        }
+
+        let err = Error::from_feedback(feedback(9942, "PIN falsch."));
+        assert_eq!(err.to_string(), "Rejected by bank: 9942 PIN falsch.");
+        match err {
+            Error::Bank { code, .. } => assert_eq!(code, ReturnCode::InvalidPin),
+            other => panic!("Expected rejection, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_transport_error() {
+        use std::error::Error as _;
+        let io_error = std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out");
+        let err = Error::Transport(Box::new(io_error));
+        assert_eq!(err.to_string(), "Transport error: timed out");
+        assert_eq!(err.source().unwrap().to_string(), "timed out");
+    }
+}
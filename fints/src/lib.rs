@@ -1,12 +1,33 @@
+// Lets code generated by fints_derive refer to this crate as `::fints` from inside it as well.
+extern crate self as fints;
+
+pub mod balance;
+pub mod camt;
 pub mod client;
 pub mod data_types;
+pub mod de;
 pub mod dialog;
+pub mod error;
 pub mod messages;
+pub mod mt940;
+pub mod pain001;
+pub mod parameters;
 pub mod se;
 pub mod segments;
+pub mod state;
+pub mod transaction;
+pub mod transfer;
+pub mod transport;
 pub mod utils;
 
-pub use crate::client::PinTanClient;
-pub use crate::dialog::Dialog;
-pub use crate::messages::{Msg_DialogSync, Msg_DialogInit};
+pub use crate::balance::Balance;
+pub use crate::client::{
+    JobStatus, OpenDialog, PinTanClient, SepaAccount, TanChallenge, Transactions,
+};
+pub use crate::dialog::{Dialog, DialogState};
+pub use crate::error::Error;
+pub use crate::state::ClientState;
+pub use crate::transaction::{Transaction, TransactionStatus};
+pub use crate::transfer::{Recipient, Transfer};
+pub use crate::transport::{HttpTransport, Transport};
 pub use fints_derive::Message;
@@ -1,200 +1,721 @@
-use crate::se::to_string;
+use crate::se::{self, to_bytes};
 use chrono::prelude::*;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
-use serde_derive::{Deserialize, Serialize};
+use serde::Serialize;
+use std::fmt::Debug;
 
 use crate::data_types::*;
+use crate::de::{self, RawMessage};
+use crate::dialog::Dialog;
 use crate::segments::*;
-use fints_derive::Message;
 
 pub trait Message {
-    fn prepare_message_for_sending(&self) -> String;
-}
-
-#[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize, Message)]
-pub struct Msg_DialogSync {
-    message_head: Seg_HNHBK_MessageHead,
-    signature_head: Seg_HNSHK_SignatureHead,
-    identification: Seg_HKIDN_Identification,
-    processing_preparation: Seg_HKVVB_ProcessingPreparation,
-    two_step_tan_submission: Option<Seg_HKTAN_TwoStepTanSubmission>,
-    request_for_pubkey: Option<Seg_HKISA_RequestForPubkey>,
-    synchronization: Seg_HKSYN_Synchronization,
-    signature_end: Seg_HNSHA_SignatureEnd,
-    message_end: Seg_HNHBS_MessageEnd,
-}
-
-impl Msg_DialogSync {
-    pub fn new(
-        bank_code: u32,
-        username: &str,
-        pin: &str,
-        customer_system_id: &str,
-        message_no: u16,
-    ) -> Msg_DialogSync {
-        let security_reference: String = thread_rng().sample_iter(&Alphanumeric).take(14).collect();
-        let hnhbk_message_head = Seg_HNHBK_MessageHead {
-            segment_head: DEG_SegmentHead {
-                identifier: "HNHBK".to_string(),
-                segment_no: 1,
-                version: 3,
-                reference_seg: None,
-            },
-            message_size: 0,
-            hbci_version: 300,
-            dialog_id: "0".to_string(),
-            message_no,
-            reference_msg: None,
-        };
+    fn number_segments(&mut self);
+    fn prepare_message_for_sending(&mut self) -> Result<String, se::Error>;
+}
+
+/// The segments held by a field of a message.
+///
+/// This is used by `#[derive(Message)]` to number segments in order. Absent optional segments
+/// don't get a number.
+pub trait Segments {
+    /// Number all segments consecutively, `segment_no` is the number of the previous segment.
+    fn number_segments(&mut self, segment_no: &mut u16);
+
+    /// Number of the first segment, if any.
+    fn first_segment_no(&self) -> Option<u16>;
+
+    fn set_reference_seg(&mut self, reference_seg: Option<u16>);
+}
+
+impl<T: Segment> Segments for T {
+    fn number_segments(&mut self, segment_no: &mut u16) {
+        *segment_no += 1;
+        self.segment_head_mut().segment_no = *segment_no;
+    }
+
+    fn first_segment_no(&self) -> Option<u16> {
+        Some(self.segment_head().segment_no)
+    }
 
-        let hnshk_signature_head = Seg_HNSHK_SignatureHead {
-            segment_head: DEG_SegmentHead {
-                identifier: "HNSHK".to_string(),
-                segment_no: 2,
-                version: 4,
-                reference_seg: None,
+    fn set_reference_seg(&mut self, reference_seg: Option<u16>) {
+        self.segment_head_mut().reference_seg = reference_seg;
+    }
+}
+
+impl<T: Segment> Segments for Option<T> {
+    fn number_segments(&mut self, segment_no: &mut u16) {
+        if let Some(segment) = self {
+            segment.number_segments(segment_no);
+        }
+    }
+
+    fn first_segment_no(&self) -> Option<u16> {
+        self.as_ref()?.first_segment_no()
+    }
+
+    fn set_reference_seg(&mut self, reference_seg: Option<u16>) {
+        if let Some(segment) = self {
+            segment.set_reference_seg(reference_seg);
+        }
+    }
+}
+
+impl<T: Segment> Segments for Vec<T> {
+    fn number_segments(&mut self, segment_no: &mut u16) {
+        for segment in self {
+            segment.number_segments(segment_no);
+        }
+    }
+
+    fn first_segment_no(&self) -> Option<u16> {
+        self.first()?.first_segment_no()
+    }
+
+    fn set_reference_seg(&mut self, reference_seg: Option<u16>) {
+        for segment in self {
+            segment.set_reference_seg(reference_seg);
+        }
+    }
+}
+
+/// Random reference tying a signature head to its signature end.
+fn new_security_reference() -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(14)
+        .map(char::from)
+        .collect()
+}
+
+fn new_message_head(dialog_id: &str, message_no: u16) -> Seg_HNHBK_MessageHead {
+    Seg_HNHBK_MessageHead {
+        segment_head: Seg_HNHBK_MessageHead::new_segment_head(0),
+        message_size: 0,
+        hbci_version: 300,
+        dialog_id: dialog_id.to_string(),
+        message_no,
+        reference_msg: None,
+    }
+}
+
+fn new_signature_head(
+    bank_code: u32,
+    username: &str,
+    customer_system_id: &str,
+    security_reference: &str,
+    security_function: SecurityFunction,
+) -> Seg_HNSHK_SignatureHead {
+    Seg_HNSHK_SignatureHead {
+        segment_head: Seg_HNSHK_SignatureHead::new_segment_head(0),
+        security_profile: DEG_SecurityProfile {
+            security_method_code: SecurityMethodCode::PIN,
+            version: 1, // TODO This should be upgraded as soon as a better version is available.
+        },
+        security_function,
+        security_reference: security_reference.to_string(),
+        security_area: SecurityArea::SHM,
+        security_role: SecurityRole::ISS,
+        security_identification_details: DEG_SecurityIdentificationDetails {
+            security_party_identifier: SecurityPartyIdentifier::MS,
+            cardholder_identification: None,
+            party_identifier: Some(customer_system_id.to_string()),
+        },
+        security_ref_no: 1,
+        security_date: DEG_SecurityDate {
+            date_identifier: DateIdentifier::STS,
+            date: Local::now().naive_local().date(),
+            time: Local::now().naive_local().time(),
+        },
+        hash_algorithm: DEG_HashAlgorithm {
+            use_of_hash_algorithm: UseOfHashAlgorithm::OHA,
+            hash_algorithm: HashAlgorithm::MutuallyAgreed,
+            hash_algorithm_param_identifier: HashAlgorithmParameterIdentifier::IVC,
+            param_value: None,
+        },
+        signature_algorithm: DEG_SignatureAlgorithm {
+            use_of_signature_algorithm: UseOfSignatureAlgorithm::OSG,
+            signature_algorithm: SignatureAlgorithm::RSA,
+            operation_mode: OperationMode::ISO_97961,
+        },
+        key_name: DEG_KeyName {
+            institute_identifier: DEG_InstituteIdentifier {
+                country_code: "280".to_string(), // TODO This is Germany according to https://www.girocard.eu/media/weiternutzung_iso3166-code-280_deutschland.pdf
+                bank_code,
             },
-            security_profile: DEG_SecurityProfile {
-                security_method_code: SecurityMethodCode::PIN,
-                version: 1, // TODO This should be upgraded as soon as a better version is available.
+            user_id: username.to_string(),
+            key_type: KeyType::S,
+            key_no: 0,
+            key_version: 0,
+        },
+        certificate: None,
+    }
+}
+
+/// Identification (HKIDN) of the user and customer system.
+pub(crate) fn new_identification(
+    bank_code: u32,
+    username: &str,
+    customer_system_id: &str,
+) -> Seg_HKIDN_Identification {
+    Seg_HKIDN_Identification {
+        segment_head: Seg_HKIDN_Identification::new_segment_head(0),
+        institute_identifier: DEG_InstituteIdentifier {
+            country_code: "280".to_string(), // TODO This is Germany according to https://www.girocard.eu/media/weiternutzung_iso3166-code-280_deutschland.pdf
+            bank_code,
+        },
+        customer_id: username.to_string(),
+        customer_system_id: customer_system_id.to_string(),
+        customer_system_status: CustomerSystemStatus::Required,
+    }
+}
+
+/// Processing preparation (HKVVB) announcing the BPD and UPD versions we know about.
+pub(crate) fn new_processing_preparation(
+    bpd_version: u16,
+    upd_version: u16,
+) -> Seg_HKVVB_ProcessingPreparation {
+    Seg_HKVVB_ProcessingPreparation {
+        segment_head: Seg_HKVVB_ProcessingPreparation::new_segment_head(0),
+        bpd_version,
+        upd_version,
+        dialog_lang: DialogLang::de,
+        product_identifier: "fints-rs".to_string(), // TODO: Make configurable
+        product_version: "0.1".to_string(),         // TODO: Make configurable
+    }
+}
+
+/// Synchronization (HKSYN) requesting a new customer system id.
+pub(crate) fn new_synchronization() -> Seg_HKSYN_Synchronization {
+    Seg_HKSYN_Synchronization {
+        segment_head: Seg_HKSYN_Synchronization::new_segment_head(0),
+        synchronization_mode: SynchronizationMode::ReportNewCustomerSystemId,
+    }
+}
+
+/// Dialog end (HKEND) for the dialog with the given id.
+pub(crate) fn new_dialog_end(dialog_id: &str) -> Seg_HKEND_DialogEnd {
+    Seg_HKEND_DialogEnd {
+        segment_head: Seg_HKEND_DialogEnd::new_segment_head(0),
+        dialog_id: dialog_id.to_string(),
+    }
+}
+
+/// SEPA account info (HKSPA) for all accounts of the user.
+pub(crate) fn new_sepa_account_info() -> Seg_HKSPA_SepaAccountInfo1 {
+    Seg_HKSPA_SepaAccountInfo1 {
+        segment_head: Seg_HKSPA_SepaAccountInfo1::new_segment_head(0),
+        accounts: vec![],
+    }
+}
+
+/// Two-step TAN submission (HKTAN), either starting the TAN process for the segment
+/// `segment_identifier` or submitting the TAN for the job with the given `job_reference`.
+pub(crate) fn new_tan_submission(
+    tan_process: TanProcess,
+    segment_identifier: Option<&str>,
+    job_reference: Option<&str>,
+) -> Seg_HKTAN_TwoStepTanSubmission {
+    let further_tan_follows = match tan_process {
+        TanProcess::TanSubmission => Some(false),
+        _ => None,
+    };
+    Seg_HKTAN_TwoStepTanSubmission {
+        segment_head: Seg_HKTAN_TwoStepTanSubmission::new_segment_head(0),
+        tan_process,
+        segment_identifier: segment_identifier.map(str::to_string),
+        account_international_issuer: None,
+        job_hash_value: None,
+        job_reference: job_reference.map(str::to_string),
+        further_tan_follows,
+    }
+}
+
+fn new_signature_end(
+    security_reference: &str,
+    pin: &str,
+    tan: Option<&str>,
+) -> Seg_HNSHA_SignatureEnd {
+    Seg_HNSHA_SignatureEnd {
+        segment_head: Seg_HNSHA_SignatureEnd::new_segment_head(0),
+        security_reference: security_reference.to_string(),
+        validation_result: None,
+        user_defined_signature: Some(DEG_UserDefinedSignature {
+            PIN: pin.to_string(),
+            TAN: tan.map(str::to_string),
+        }),
+    }
+}
+
+/// The dummy encryption head PIN/TAN messages are wrapped in.
+fn new_encryption_head(
+    bank_code: u32,
+    username: &str,
+    customer_system_id: &str,
+) -> Seg_HNVSK_EncryptionHead {
+    Seg_HNVSK_EncryptionHead {
+        segment_head: Seg_HNVSK_EncryptionHead::new_segment_head(ENCRYPTION_HEAD_SEGMENT_NO),
+        security_profile: DEG_SecurityProfile {
+            security_method_code: SecurityMethodCode::PIN,
+            version: 1,
+        },
+        security_function: SecurityFunction::PinTanEncryption,
+        security_role: SecurityRole::ISS,
+        security_identification_details: DEG_SecurityIdentificationDetails {
+            security_party_identifier: SecurityPartyIdentifier::MS,
+            cardholder_identification: None,
+            party_identifier: Some(customer_system_id.to_string()),
+        },
+        security_date: DEG_SecurityDate {
+            date_identifier: DateIdentifier::STS,
+            date: Local::now().naive_local().date(),
+            time: Local::now().naive_local().time(),
+        },
+        encryption_algorithm: DEG_EncryptionAlgorithm {
+            use_of_encryption_algorithm: UseOfEncryptionAlgorithm::OSY,
+            operation_mode: OperationMode::CBC,
+            encryption_algorithm: EncryptionAlgorithm::TwoKeyTripleDES,
+            key_param_value: vec![0; 8],
+            key_param_identifier: KeyParameterIdentifier::KYE,
+            iv_param_identifier: IvParameterIdentifier::IVC,
+            iv_param_value: None,
+        },
+        key_name: DEG_KeyName {
+            institute_identifier: DEG_InstituteIdentifier {
+                country_code: "280".to_string(), // TODO This is Germany according to https://www.girocard.eu/media/weiternutzung_iso3166-code-280_deutschland.pdf
+                bank_code,
             },
-            security_function: SecurityFunction::SingleStepAuth,
-            security_reference: security_reference.clone(),
-            security_area: SecurityArea::SHM,
-            security_role: SecurityRole::ISS,
-            security_identification_details: DEG_SecurityIdentificationDetails {
-                security_party_identifier: SecurityPartyIdentifier::MS,
-                cardholder_identification: None,
-                party_identifier: Some(customer_system_id.to_string()),
+            user_id: username.to_string(),
+            key_type: KeyType::V,
+            key_no: 0,
+            key_version: 0,
+        },
+        compression_function: CompressionFunction::NULL,
+        certificate: None,
+    }
+}
+
+fn new_message_end(message_no: u16) -> Seg_HNHBS_MessageEnd {
+    Seg_HNHBS_MessageEnd {
+        segment_head: Seg_HNHBS_MessageEnd::new_segment_head(0),
+        message_no,
+    }
+}
+
+/// The encryption head and encrypted data always have these fixed segment numbers.
+const ENCRYPTION_HEAD_SEGMENT_NO: u16 = 998;
+const ENCRYPTED_DATA_SEGMENT_NO: u16 = 999;
+
+/// Replace the encryption envelope (HNVSK and HNVSD) of a received message by the segments
+/// it contains. Messages without envelope are returned as they are.
+pub fn decrypt(message: RawMessage) -> Result<RawMessage, de::Error> {
+    let encrypted_data: Seg_HNVSD_EncryptedData = match message.find(Seg_HNVSD_EncryptedData::ID) {
+        Some(segment) => segment.deserialize()?,
+        None => return Ok(message),
+    };
+    let mut inner = RawMessage::from_bytes(&encrypted_data.encrypted_data)?.segments;
+
+    let mut segments = Vec::with_capacity(message.segments.len() + inner.len());
+    for segment in message.segments {
+        match segment.identifier() {
+            Seg_HNVSK_EncryptionHead::ID => {}
+            Seg_HNVSD_EncryptedData::ID => segments.append(&mut inner),
+            _ => segments.push(segment),
+        }
+    }
+    Ok(RawMessage { segments })
+}
+
+/// A business segment that can be put into a message using [`MessageBuilder`].
+pub trait BusinessSegment: Debug {
+    fn set_segment_no(&mut self, segment_no: u16);
+
+    fn serialize_segment(&self) -> Result<Vec<u8>, se::Error>;
+}
+
+impl<T> BusinessSegment for T
+where
+    T: Segment + Serialize + Debug,
+{
+    fn set_segment_no(&mut self, segment_no: u16) {
+        self.segment_head_mut().segment_no = segment_no;
+    }
+
+    fn serialize_segment(&self) -> Result<Vec<u8>, se::Error> {
+        to_bytes(self)
+    }
+}
+
+/// Builds a signed message around any number of business segments.
+///
+/// The message head (HNHBK), signature head (HNSHK), signature end (HNSHA) and message end
+/// (HNHBS) are added automatically and filled in from the dialog. Segments are numbered in the
+/// order they were added. Everything between message head and message end is wrapped in the
+/// encryption envelope (HNVSK and HNVSD).
+#[derive(Debug)]
+pub struct MessageBuilder<'a> {
+    dialog: &'a Dialog,
+    segments: Vec<Box<dyn BusinessSegment + 'a>>,
+    tan: Option<&'a str>,
+}
+
+impl<'a> MessageBuilder<'a> {
+    pub fn new(dialog: &'a Dialog) -> MessageBuilder<'a> {
+        MessageBuilder {
+            dialog,
+            segments: vec![],
+            tan: None,
+        }
+    }
+
+    /// Sign the message with a TAN in addition to the PIN.
+    pub fn tan(mut self, tan: &'a str) -> MessageBuilder<'a> {
+        self.tan = Some(tan);
+        self
+    }
+
+    /// Add a business segment to the message.
+    pub fn segment<S>(mut self, segment: S) -> MessageBuilder<'a>
+    where
+        S: BusinessSegment + 'a,
+    {
+        self.segments.push(Box::new(segment));
+        self
+    }
+
+    /// Build the message in its wire format.
+    pub fn build(mut self) -> Result<Vec<u8>, se::Error> {
+        let dialog = self.dialog;
+        let security_reference = new_security_reference();
+
+        let mut signature_head = new_signature_head(
+            dialog.bank_code,
+            &dialog.username,
+            &dialog.client_state.customer_system_id,
+            &security_reference,
+            dialog.client_state.security_function(),
+        );
+        signature_head.segment_head.segment_no = 2;
+        let mut signed = to_bytes(&signature_head)?;
+
+        let mut segment_no = 2;
+        for segment in &mut self.segments {
+            segment_no += 1;
+            segment.set_segment_no(segment_no);
+            signed.extend(segment.serialize_segment()?);
+        }
+
+        let mut signature_end = new_signature_end(&security_reference, &dialog.pin, self.tan);
+        signature_end.segment_head.segment_no = segment_no + 1;
+        signed.extend(to_bytes(&signature_end)?);
+
+        let encryption_head = new_encryption_head(
+            dialog.bank_code,
+            &dialog.username,
+            &dialog.client_state.customer_system_id,
+        );
+        let mut body = to_bytes(&encryption_head)?;
+        let encrypted_data = Seg_HNVSD_EncryptedData {
+            segment_head: Seg_HNVSD_EncryptedData::new_segment_head(ENCRYPTED_DATA_SEGMENT_NO),
+            encrypted_data: signed,
+        };
+        body.extend(to_bytes(&encrypted_data)?);
+
+        let mut message_end = new_message_end(dialog.message_no);
+        message_end.segment_head.segment_no = segment_no + 2;
+        body.extend(to_bytes(&message_end)?);
+
+        // The message size is always serialized with the same width so we can measure the
+        // message head before knowing the actual size.
+        let mut message_head = new_message_head(&dialog.dialog_id, dialog.message_no);
+        message_head.segment_head.segment_no = 1;
+        let head_size = to_bytes(&message_head)?.len();
+        message_head.message_size = (head_size + body.len()) as u64;
+
+        let mut message = to_bytes(&message_head)?;
+        message.extend(body);
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::se::to_string;
+    use fints_derive::Message;
+
+    /// Synchronization with the plain layout of signed segments, banks expect them encrypted.
+    #[derive(Debug, Message)]
+    struct PlainSync {
+        message_head: Seg_HNHBK_MessageHead,
+        signature_head: Seg_HNSHK_SignatureHead,
+        identification: Seg_HKIDN_Identification,
+        processing_preparation: Seg_HKVVB_ProcessingPreparation,
+        two_step_tan_submission: Option<Seg_HKTAN_TwoStepTanSubmission>,
+        request_for_pubkey: Option<Seg_HKISA_RequestForPubkey>,
+        synchronization: Seg_HKSYN_Synchronization,
+        signature_end: Seg_HNSHA_SignatureEnd,
+        message_end: Seg_HNHBS_MessageEnd,
+    }
+
+    impl PlainSync {
+        fn new(
+            bank_code: u32,
+            username: &str,
+            pin: &str,
+            customer_system_id: &str,
+            message_no: u16,
+        ) -> PlainSync {
+            let security_reference = new_security_reference();
+            let hnhbk_message_head = new_message_head("0", message_no);
+            let hnshk_signature_head = new_signature_head(
+                bank_code,
+                username,
+                customer_system_id,
+                &security_reference,
+                SecurityFunction::SingleStepAuth,
+            );
+
+            let hkidn_identification = new_identification(bank_code, username, customer_system_id);
+            let hkvvb_processing_preparation = new_processing_preparation(0, 0);
+            let hksyn_synchronization = new_synchronization();
+
+            let hnsha_signature_end = new_signature_end(&security_reference, pin, None);
+            let hnhbs_message_end = new_message_end(message_no);
+
+            let mut message = PlainSync {
+                message_head: hnhbk_message_head,
+                signature_head: hnshk_signature_head,
+                identification: hkidn_identification,
+                processing_preparation: hkvvb_processing_preparation,
+                two_step_tan_submission: None,
+                request_for_pubkey: None,
+                synchronization: hksyn_synchronization,
+                signature_end: hnsha_signature_end,
+                message_end: hnhbs_message_end,
+            };
+            // Segment numbers depend on which optional segments are present.
+            message.number_segments();
+            message
+        }
+    }
+
+    #[test]
+    fn test_message_serialize() {
+        let message = PlainSync::new(12345678, "test1", "1234", "0", 1);
+        let serialized = to_bytes(&message).unwrap();
+        let parsed = RawMessage::from_bytes(&serialized).unwrap();
+        let identifiers: Vec<&str> = parsed.segments.iter().map(|s| s.identifier()).collect();
+        assert_eq!(
+            identifiers,
+            vec!["HNHBK", "HNSHK", "HKIDN", "HKVVB", "HKSYN", "HNSHA", "HNHBS"]
+        );
+        let raw_identification = parsed.find(Seg_HKIDN_Identification::ID).unwrap();
+        assert_eq!(raw_identification.segment_no(), Some(3));
+        assert_eq!(
+            raw_identification.version(),
+            Some(Seg_HKIDN_Identification::VERSION)
+        );
+        let identification: Seg_HKIDN_Identification = raw_identification.deserialize().unwrap();
+        assert_eq!(identification.institute_identifier.bank_code, 12345678);
+        assert_eq!(identification.customer_id, "test1");
+    }
+
+    #[test]
+    fn test_message_escaping() {
+        let message = PlainSync::new(12345678, "user+1", "p?n:'@", "0", 1);
+        let serialized = to_string(&message).unwrap();
+        assert!(serialized.contains("+user?+1+"));
+        assert!(serialized.contains("+p??n?:?'?@'"));
+
+        let parsed = RawMessage::from_bytes(&to_bytes(&message).unwrap()).unwrap();
+        assert_eq!(parsed.segments.len(), 7);
+        let signature_end: Seg_HNSHA_SignatureEnd =
+            parsed.find("HNSHA").unwrap().deserialize().unwrap();
+        assert_eq!(signature_end.user_defined_signature.unwrap().PIN, "p?n:'@");
+    }
+
+    #[test]
+    fn test_message_size() {
+        let mut message = PlainSync::new(12345678, "test1", "1234", "0", 1);
+        let encoded = message.prepare_message_for_sending().unwrap();
+        let decoded = base64::decode(&encoded).unwrap();
+        let parsed = RawMessage::from_bytes(&decoded).unwrap();
+        let message_head: Seg_HNHBK_MessageHead =
+            parsed.find("HNHBK").unwrap().deserialize().unwrap();
+        assert_eq!(message_head.message_size, decoded.len() as u64);
+        assert_eq!(message.message_head.message_size, decoded.len() as u64);
+    }
+
+    #[test]
+    fn test_message_segment_numbers() {
+        let mut message = PlainSync::new(12345678, "test1", "1234", "0", 1);
+        message.two_step_tan_submission = Some(Seg_HKTAN_TwoStepTanSubmission {
+            segment_head: Seg_HKTAN_TwoStepTanSubmission::new_segment_head(0),
+            tan_process: TanProcess::Available,
+            segment_identifier: Some(Seg_HKIDN_Identification::ID.to_string()),
+            account_international_issuer: None,
+            job_hash_value: None,
+            job_reference: None,
+            further_tan_follows: None,
+        });
+        message.number_segments();
+
+        let parsed = RawMessage::from_bytes(&to_bytes(&message).unwrap()).unwrap();
+        let numbers: Vec<(&str, Option<u16>)> = parsed
+            .segments
+            .iter()
+            .map(|s| (s.identifier(), s.segment_no()))
+            .collect();
+        assert_eq!(
+            numbers,
+            vec![
+                ("HNHBK", Some(1)),
+                ("HNSHK", Some(2)),
+                ("HKIDN", Some(3)),
+                ("HKVVB", Some(4)),
+                ("HKTAN", Some(5)),
+                ("HKSYN", Some(6)),
+                ("HNSHA", Some(7)),
+                ("HNHBS", Some(8)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_message_reference_seg() {
+        #[derive(Debug, Message)]
+        struct Referencing {
+            message_head: Seg_HNHBK_MessageHead,
+            pubkey: Option<Seg_HKISA_RequestForPubkey>,
+            synchronization: Seg_HKSYN_Synchronization,
+            #[fints(reference = "synchronization")]
+            message_end: Seg_HNHBS_MessageEnd,
+        }
+
+        let mut message = Referencing {
+            message_head: Seg_HNHBK_MessageHead {
+                segment_head: Seg_HNHBK_MessageHead::new_segment_head(0),
+                message_size: 0,
+                hbci_version: 300,
+                dialog_id: "0".to_string(),
+                message_no: 1,
+                reference_msg: None,
             },
-            security_ref_no: 1,
-            security_date: DEG_SecurityDate {
-                date_identifier: DateIdentifier::STS,
-                date: Local::today().naive_local(),
-                time: Local::now().naive_local().time(),
+            pubkey: None,
+            synchronization: Seg_HKSYN_Synchronization {
+                segment_head: Seg_HKSYN_Synchronization::new_segment_head(0),
+                synchronization_mode: SynchronizationMode::ReportNewCustomerSystemId,
             },
-            hash_algorithm: DEG_HashAlgorithm {
-                use_of_hash_algorithm: UseOfHashAlgorithm::OHA,
-                hash_algorithm: HashAlgorithm::MutuallyAgreed,
-                hash_algorithm_param_identifier: HashAlgorithmParameterIdentifier::IVC,
-                param_value: None,
+            message_end: Seg_HNHBS_MessageEnd {
+                segment_head: Seg_HNHBS_MessageEnd::new_segment_head(0),
+                message_no: 1,
             },
-            signature_algorithm: DEG_SignatureAlgorithm {
-                use_of_signature_algorithm: UseOfSignatureAlgorithm::OSG,
-                signature_algorithm: SignatureAlgorithm::RSA,
-                operation_mode: OperationMode::ISO_97961,
+        };
+        message.number_segments();
+        assert_eq!(message.synchronization.segment_head.segment_no, 2);
+        assert_eq!(message.message_end.segment_head.segment_no, 3);
+        assert_eq!(message.message_end.segment_head.reference_seg, Some(2));
+    }
+
+    #[test]
+    fn test_message_builder() {
+        let mut dialog = Dialog::new(12345678, "test1", "1234");
+        dialog.dialog_id = "abc".to_string();
+        dialog.message_no = 2;
+        let synchronization = Seg_HKSYN_Synchronization {
+            segment_head: Seg_HKSYN_Synchronization::new_segment_head(0),
+            synchronization_mode: SynchronizationMode::ReportNewCustomerSystemId,
+        };
+        let pubkey = Seg_HKISA_RequestForPubkey {
+            segment_head: Seg_HKISA_RequestForPubkey::new_segment_head(0),
+            message_relationship: MessageRelationship::ExpectAnswer,
+            function_type_identifier: FunctionTypeIdentifier::CertficateStatusRequest,
+            security_profile: DEG_SecurityProfile {
+                security_method_code: SecurityMethodCode::PIN,
+                version: 1,
             },
             key_name: DEG_KeyName {
                 institute_identifier: DEG_InstituteIdentifier {
-                    country_code: "280".to_string(), // TODO This is Germany according to https://www.girocard.eu/media/weiternutzung_iso3166-code-280_deutschland.pdf
-                    bank_code: bank_code,
+                    country_code: "280".to_string(),
+                    bank_code: 12345678,
                 },
-                user_id: username.to_string(),
+                user_id: "test1".to_string(),
                 key_type: KeyType::S,
                 key_no: 0,
                 key_version: 0,
             },
             certificate: None,
         };
+        let message = MessageBuilder::new(&dialog)
+            .segment(synchronization)
+            .segment(pubkey)
+            .build()
+            .unwrap();
 
-        let hkidn_identification = Seg_HKIDN_Identification {
-            segment_head: DEG_SegmentHead {
-                identifier: "HKIDN".to_string(),
-                segment_no: 3,
-                version: 2,
-                reference_seg: None,
-            },
-            institute_identifier: DEG_InstituteIdentifier {
-                country_code: "280".to_string(), // TODO This is Germany according to https://www.girocard.eu/media/weiternutzung_iso3166-code-280_deutschland.pdf
-                bank_code: bank_code,
-            },
-            customer_id: username.to_string(),
-            customer_system_id: customer_system_id.to_string(),
-            customer_system_status: CustomerSystemStatus::Required,
-        };
+        let encrypted = RawMessage::from_bytes(&message).unwrap();
+        let numbers: Vec<(&str, Option<u16>)> = encrypted
+            .segments
+            .iter()
+            .map(|s| (s.identifier(), s.segment_no()))
+            .collect();
+        assert_eq!(
+            numbers,
+            vec![
+                ("HNHBK", Some(1)),
+                ("HNVSK", Some(998)),
+                ("HNVSD", Some(999)),
+                ("HNHBS", Some(6)),
+            ]
+        );
+        let encryption_head: Seg_HNVSK_EncryptionHead =
+            encrypted.find("HNVSK").unwrap().deserialize().unwrap();
+        assert_eq!(
+            encryption_head.encryption_algorithm.key_param_value,
+            vec![0; 8]
+        );
 
-        let hkvvb_processing_preparation = Seg_HKVVB_ProcessingPreparation {
-            segment_head: DEG_SegmentHead {
-                identifier: "HKVVB".to_string(),
-                segment_no: 4,
-                version: 3,
-                reference_seg: None,
-            },
-            bpd_version: 0,
-            upd_version: 0,
-            dialog_lang: DialogLang::de,
-            product_identifier: "fints-rs".to_string(), // TODO: Make configurable
-            product_version: "0.1".to_string(),         // TODO: Make configurable
-        };
+        let parsed = decrypt(encrypted).unwrap();
+        let numbers: Vec<(&str, Option<u16>)> = parsed
+            .segments
+            .iter()
+            .map(|s| (s.identifier(), s.segment_no()))
+            .collect();
+        assert_eq!(
+            numbers,
+            vec![
+                ("HNHBK", Some(1)),
+                ("HNSHK", Some(2)),
+                ("HKSYN", Some(3)),
+                ("HKISA", Some(4)),
+                ("HNSHA", Some(5)),
+                ("HNHBS", Some(6)),
+            ]
+        );
 
-        let hksyn_synchronization = Seg_HKSYN_Synchronization {
-            segment_head: DEG_SegmentHead {
-                identifier: "HKSYN".to_string(),
-                segment_no: 5,
-                version: 3,
-                reference_seg: None,
-            },
-            synchronization_mode: SynchronizationMode::ReportNewCustomerSystemId,
-        };
+        let message_head: Seg_HNHBK_MessageHead =
+            parsed.find("HNHBK").unwrap().deserialize().unwrap();
+        assert_eq!(message_head.message_size, message.len() as u64);
+        assert_eq!(message_head.dialog_id, "abc");
+        assert_eq!(message_head.message_no, 2);
 
-        let hnsha_signature_end = Seg_HNSHA_SignatureEnd {
-            segment_head: DEG_SegmentHead {
-                identifier: "HNSHA".to_string(),
-                segment_no: 6,
-                version: 2,
-                reference_seg: None,
-            },
-            security_reference: security_reference.clone(),
-            validation_result: None,
-            user_defined_signature: Some(DEG_UserDefinedSignature {
-                PIN: pin.to_string(),
-                TAN: None,
-            }),
-        };
-
-        let hnhbs_message_end = Seg_HNHBS_MessageEnd {
-            segment_head: DEG_SegmentHead {
-                identifier: "HNHBS".to_string(),
-                segment_no: 7,
-                version: 1,
-                reference_seg: None,
-            },
-            message_no,
-        };
-
-        Msg_DialogSync {
-            message_head: hnhbk_message_head,
-            signature_head: hnshk_signature_head,
-            identification: hkidn_identification,
-            processing_preparation: hkvvb_processing_preparation,
-            two_step_tan_submission: None,
-            request_for_pubkey: None,
-            synchronization: hksyn_synchronization,
-            signature_end: hnsha_signature_end,
-            message_end: hnhbs_message_end,
-        }
+        let signature_head: Seg_HNSHK_SignatureHead =
+            parsed.find("HNSHK").unwrap().deserialize().unwrap();
+        let signature_end: Seg_HNSHA_SignatureEnd =
+            parsed.find("HNSHA").unwrap().deserialize().unwrap();
+        assert_eq!(
+            signature_head.security_reference,
+            signature_end.security_reference
+        );
+        assert_eq!(signature_end.user_defined_signature.unwrap().PIN, "1234");
     }
-}
-
-#[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize, Message)]
-pub struct Msg_DialogInit {
-    message_head: Seg_HNHBK_MessageHead,
-    signature_head: Seg_HNSHK_SignatureHead,
-    identification: Seg_HKIDN_Identification,
-    processing_preparation: Seg_HKVVB_ProcessingPreparation,
-    two_step_tan_submission: Option<Seg_HKTAN_TwoStepTanSubmission>,
-    request_for_pubkey: Option<Seg_HKISA_RequestForPubkey>,
-    signature_end: Seg_HNSHA_SignatureEnd,
-    message_end: Seg_HNHBS_MessageEnd,
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
     #[test]
-    fn test_message_serialize() {}
+    fn test_decrypt_unencrypted_message() {
+        let message =
+            RawMessage::from_bytes(b"HNHBK:1:3+000000000042+300+abc+2'HNHBS:2:1+2'").unwrap();
+        let decrypted = decrypt(message).unwrap();
+        assert_eq!(decrypted.segments.len(), 2);
+    }
 }
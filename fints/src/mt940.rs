@@ -0,0 +1,327 @@
+//! Parser for SWIFT MT940 account statements and MT942 interim reports as returned by HKKAZ.
+//!
+//! Only the fields needed for [`Transaction`] are interpreted. The `:86:` field is split into
+//! its German structured subfields (`?00` booking text, `?20` to `?29` purpose, ...) and SEPA
+//! references such as `EREF+` are extracted from the purpose.
+
+use crate::de;
+use crate::transaction::{Transaction, TransactionStatus};
+use chrono::{Datelike, NaiveDate};
+use encoding_rs::ISO_8859_15;
+use rust_decimal::Decimal;
+use serde::de::Error as _;
+
+type Result<T> = std::result::Result<T, de::Error>;
+
+/// Parse the transactions of all statements in `input`.
+pub fn parse(input: &[u8], status: TransactionStatus) -> Result<Vec<Transaction>> {
+    let text = ISO_8859_15.decode_without_bom_handling(input).0;
+    let mut transactions: Vec<Transaction> = vec![];
+    let mut currency = String::new();
+    let mut previous_tag = String::new();
+    for (tag, value) in fields(&text) {
+        match tag.as_str() {
+            // Opening balance such as `C201230EUR1234,56`
+            "60F" | "60M" => currency = substring(&value, 7, 10)?.to_string(),
+            // Floor limit of MT942 such as `EURD0,`
+            "34F" => currency = substring(&value, 0, 3)?.to_string(),
+            "61" => transactions.push(parse_statement_line(&value, &currency, status)?),
+            // Information on the whole statement may follow the closing balance.
+            "86" if previous_tag == "61" => {
+                if let Some(transaction) = transactions.last_mut() {
+                    parse_details(&value, transaction);
+                }
+            }
+            _ => {}
+        }
+        previous_tag = tag;
+    }
+    Ok(transactions)
+}
+
+/// Split the statements into `(tag, value)` pairs. Continuation lines are joined using `\n`.
+fn fields(text: &str) -> Vec<(String, String)> {
+    // Some banks separate lines by `@@`.
+    let text = text.replace("@@", "\n");
+    let mut fields: Vec<(String, String)> = vec![];
+    for line in text.lines() {
+        let line = line.trim_end_matches('\r');
+        if let Some((tag, value)) = split_tag(line) {
+            fields.push((tag.to_string(), value.to_string()));
+        } else if line == "-" {
+            // End of statement
+        } else if let Some((_, value)) = fields.last_mut() {
+            value.push('\n');
+            value.push_str(line);
+        }
+    }
+    fields
+}
+
+/// Split a line like `:60F:C201230EUR1234,56` into tag and value.
+fn split_tag(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix(':')?;
+    let end = rest.find(':')?;
+    let tag = &rest[..end];
+    let is_tag = (2..=3).contains(&tag.len())
+        && tag.chars().take(2).all(|c| c.is_ascii_digit())
+        && tag.chars().skip(2).all(|c| c.is_ascii_uppercase());
+    if is_tag {
+        Some((tag, &rest[end + 1..]))
+    } else {
+        None
+    }
+}
+
+fn substring(value: &str, start: usize, end: usize) -> Result<&str> {
+    value
+        .get(start..end)
+        .ok_or_else(|| de::Error::custom(format!("Invalid MT940 field {:?}", value)))
+}
+
+/// Parse a date like `201231`.
+fn parse_date(value: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(&format!("20{}", value), "%Y%m%d")
+        .map_err(|_| de::Error::custom(format!("Invalid MT940 date {:?}", value)))
+}
+
+/// Parse the statement line (`:61:`), e.g. `2012311231DR10,50NMSCNONREF//BANKREF`.
+fn parse_statement_line(
+    value: &str,
+    currency: &str,
+    status: TransactionStatus,
+) -> Result<Transaction> {
+    let line = value.lines().next().unwrap_or_default();
+    let value_date = parse_date(substring(line, 0, 6)?)?;
+    let mut rest = &line[6..];
+
+    // The booking date has no year. It may be in the year before or after the value date.
+    let mut booking_date = None;
+    let is_booking_date = rest
+        .get(..4)
+        .is_some_and(|date| date.chars().all(|c| c.is_ascii_digit()));
+    if is_booking_date {
+        let mut year = value_date.year();
+        let month: u32 = rest[..2].parse().unwrap_or_default();
+        if month == 12 && value_date.month() == 1 {
+            year -= 1;
+        } else if month == 1 && value_date.month() == 12 {
+            year += 1;
+        }
+        booking_date = Some(parse_date(&format!("{:02}{}", year % 100, &rest[..4]))?);
+        rest = &rest[4..];
+    }
+
+    let (is_debit, mark_length) = if rest.starts_with("RC") {
+        (true, 2)
+    } else if rest.starts_with("RD") {
+        (false, 2)
+    } else if rest.starts_with('C') {
+        (false, 1)
+    } else if rest.starts_with('D') {
+        (true, 1)
+    } else {
+        return Err(de::Error::custom(format!(
+            "Invalid MT940 debit/credit mark in {:?}",
+            line
+        )));
+    };
+    rest = &rest[mark_length..];
+    // Optional funds code, i.e. the third character of the currency code
+    if rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        rest = &rest[1..];
+    }
+
+    let amount_length = rest
+        .find(|c: char| !c.is_ascii_digit() && c != ',')
+        .unwrap_or(rest.len());
+    let mut amount = parse_amount(&rest[..amount_length])?;
+    if is_debit {
+        amount = -amount;
+    }
+    // Skip the transaction type such as `NMSC`.
+    rest = rest.get(amount_length + 4..).unwrap_or_default();
+
+    let (customer_reference, bank_reference) = match rest.find("//") {
+        Some(index) => (&rest[..index], Some(&rest[index + 2..])),
+        None => (rest, None),
+    };
+
+    Ok(Transaction {
+        status,
+        booking_date,
+        value_date: Some(value_date),
+        amount,
+        currency: currency.to_string(),
+        customer_reference: non_empty(customer_reference),
+        bank_reference: bank_reference.and_then(non_empty),
+        ..Transaction::default()
+    })
+}
+
+fn parse_amount(value: &str) -> Result<Decimal> {
+    value
+        .replace(',', ".")
+        .trim_end_matches('.')
+        .parse()
+        .map_err(|_| de::Error::custom(format!("Invalid MT940 amount {:?}", value)))
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    if value.is_empty() || value == "NONREF" {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+/// Parse the information to the account owner (`:86:`) into `transaction`.
+///
+/// Structured information starts with the three digit business transaction code followed by
+/// subfields, each introduced by a separator (usually `?`) and a two digit field number.
+fn parse_details(value: &str, transaction: &mut Transaction) {
+    let value = value.replace('\n', "");
+    let is_structured = value.len() > 3
+        && value
+            .get(..3)
+            .is_some_and(|code| code.chars().all(|c| c.is_ascii_digit()))
+        && !value[3..].starts_with(|c: char| c.is_alphanumeric());
+    if !is_structured {
+        set_purpose(transaction, value);
+        return;
+    }
+
+    transaction.transaction_code = Some(value[..3].to_string());
+    let separator = value[3..].chars().next().unwrap_or('?');
+    let mut purpose = String::new();
+    let mut name = String::new();
+    for subfield in value[3..].split(separator).skip(1) {
+        let (number, content) = match (subfield.get(..2), subfield.get(2..)) {
+            (Some(number), Some(content)) => (number, content),
+            _ => continue,
+        };
+        match number {
+            "00" => transaction.booking_text = non_empty(content),
+            "10" => transaction.primanota = non_empty(content),
+            "20" | "21" | "22" | "23" | "24" | "25" | "26" | "27" | "28" | "29" | "60" | "61"
+            | "62" | "63" => purpose.push_str(content),
+            "30" => transaction.counterparty_bic = non_empty(content),
+            "31" => transaction.counterparty_iban = non_empty(content),
+            "32" | "33" => name.push_str(content),
+            _ => {}
+        }
+    }
+    transaction.counterparty_name = non_empty(&name);
+    set_purpose(transaction, purpose);
+}
+
+/// Identifiers of the SEPA references in the purpose.
+const SEPA_IDENTIFIERS: &[&str] = &[
+    "EREF+", "KREF+", "MREF+", "CRED+", "DEBT+", "COAM+", "OAMT+", "SVWZ+", "ABWA+", "ABWE+",
+];
+
+/// Set the purpose, moving SEPA references like `EREF+` to their own fields.
+fn set_purpose(transaction: &mut Transaction, purpose: String) {
+    let mut positions: Vec<(usize, &str)> = SEPA_IDENTIFIERS
+        .iter()
+        .filter_map(|identifier| Some((purpose.find(identifier)?, *identifier)))
+        .collect();
+    if positions.is_empty() {
+        transaction.purpose = non_empty(purpose.trim());
+        return;
+    }
+    positions.sort_unstable();
+
+    let mut remaining = purpose[..positions[0].0].trim().to_string();
+    for (i, (start, identifier)) in positions.iter().enumerate() {
+        let end = positions.get(i + 1).map_or(purpose.len(), |(end, _)| *end);
+        let content = purpose[start + identifier.len()..end].trim();
+        match *identifier {
+            "EREF+" => transaction.end_to_end_reference = non_empty(content),
+            "MREF+" => transaction.mandate_reference = non_empty(content),
+            "CRED+" => transaction.creditor_id = non_empty(content),
+            "SVWZ+" => remaining = content.to_string(),
+            _ => {}
+        }
+    }
+    transaction.purpose = non_empty(&remaining);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::str::FromStr;
+
+    fn date(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(year, month, day)
+    }
+
+    #[test]
+    fn test_parse_statement() {
+        let input = b"\r\n:20:STARTUMS\r\n\
+              :25:12345678/0001234567\r\n\
+              :28C:0\r\n\
+              :60F:C201230EUR1234,56\r\n\
+              :61:2012310102DR10,50NMSCNONREF//BANK1\r\n\
+              :86:106?00SEPA-LASTSCHRIFT?109310?20EREF+E2E-1?21MREF+M-1?22CRED+DE98ZZZ09999999999\r\n\
+              ?23SVWZ+Strom Dezember 202?240?30TESTDEFFXXX?31DE00123456780007654321?32Stadtwerke\r\n\
+              ?33 Musterstadt\r\n\
+              :61:2101010101CR100,NTRFKREF1\r\n\
+              :86:Barauszahlung\r\n\
+              :62F:C210101EUR1324,06\r\n\
+              :86:Statement information\r\n\
+              -";
+        let transactions = parse(input, TransactionStatus::Booked).unwrap();
+        assert_eq!(transactions.len(), 2);
+
+        assert_eq!(
+            transactions[0],
+            Transaction {
+                status: TransactionStatus::Booked,
+                booking_date: date(2021, 1, 2),
+                value_date: date(2020, 12, 31),
+                amount: Decimal::from_str("-10.50").unwrap(),
+                currency: "EUR".to_string(),
+                transaction_code: Some("106".to_string()),
+                booking_text: Some("SEPA-LASTSCHRIFT".to_string()),
+                primanota: Some("9310".to_string()),
+                purpose: Some("Strom Dezember 2020".to_string()),
+                counterparty_name: Some("Stadtwerke Musterstadt".to_string()),
+                counterparty_iban: Some("DE00123456780007654321".to_string()),
+                counterparty_bic: Some("TESTDEFFXXX".to_string()),
+                end_to_end_reference: Some("E2E-1".to_string()),
+                mandate_reference: Some("M-1".to_string()),
+                creditor_id: Some("DE98ZZZ09999999999".to_string()),
+                customer_reference: None,
+                bank_reference: Some("BANK1".to_string()),
+            }
+        );
+
+        let transaction = &transactions[1];
+        assert_eq!(transaction.booking_date, date(2021, 1, 1));
+        assert_eq!(transaction.amount, Decimal::from(100));
+        assert_eq!(transaction.transaction_code, None);
+        assert_eq!(transaction.purpose.as_deref(), Some("Barauszahlung"));
+        assert_eq!(transaction.customer_reference.as_deref(), Some("KREF1"));
+    }
+
+    #[test]
+    fn test_parse_interim_report() {
+        let input = b":20:STARTDISPE@@:25:12345678/0001234567@@:28C:00001/001@@:34F:EURD0,\
+              @@:13:2101011200@@:61:210104C5,NMSCNONREF@@:86:166?00GUTSCHRIFT?20Danke@@\
+              :90D:0EUR0,@@:90C:1EUR5,@@-";
+        let transactions = parse(input, TransactionStatus::Pending).unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].status, TransactionStatus::Pending);
+        assert_eq!(transactions[0].booking_date, None);
+        assert_eq!(transactions[0].amount, Decimal::from(5));
+        assert_eq!(transactions[0].currency, "EUR");
+        assert_eq!(transactions[0].purpose.as_deref(), Some("Danke"));
+    }
+
+    #[test]
+    fn test_parse_invalid_statement_line() {
+        assert!(parse(b":61:2101X", TransactionStatus::Booked).is_err());
+    }
+}
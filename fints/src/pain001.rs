@@ -0,0 +1,428 @@
+//! Generator for pain.001 customer credit transfer initiations as sent with HKCCS.
+
+use crate::client::SepaAccount;
+use crate::se;
+use crate::transfer::Transfer;
+use chrono::{Local, NaiveDate};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use rust_decimal::Decimal;
+use serde::ser::Error as _;
+use std::fmt::Write;
+
+type Result<T> = std::result::Result<T, se::Error>;
+
+/// Prefix of the descriptors of all pain.001 versions.
+pub const PAIN_001_DESCRIPTOR: &str = "urn:iso:std:iso:20022:tech:xsd:pain.001";
+
+/// The pain.001 versions we can generate, from least to most preferred.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Version {
+    /// German DK variant of pain.001.001.03.
+    V3_3,
+    V1_3,
+    V1_9,
+}
+
+impl Version {
+    /// Version of a SEPA data format descriptor as announced in HISPAS, e.g.
+    /// `urn:iso:std:iso:20022:tech:xsd:pain.001.001.03`.
+    fn from_descriptor(descriptor: &str) -> Option<Version> {
+        let descriptor = descriptor.trim_end_matches(".xsd");
+        if descriptor.ends_with("pain.001.003.03") {
+            Some(Version::V3_3)
+        } else if descriptor.ends_with("pain.001.001.03") {
+            Some(Version::V1_3)
+        } else if descriptor.ends_with("pain.001.001.09") {
+            Some(Version::V1_9)
+        } else {
+            None
+        }
+    }
+
+    fn namespace(self) -> &'static str {
+        match self {
+            Version::V3_3 => "urn:iso:std:iso:20022:tech:xsd:pain.001.003.03",
+            Version::V1_3 => "urn:iso:std:iso:20022:tech:xsd:pain.001.001.03",
+            Version::V1_9 => "urn:iso:std:iso:20022:tech:xsd:pain.001.001.09",
+        }
+    }
+
+    /// Name of the BIC element, which was renamed in later versions.
+    fn bic_element(self) -> &'static str {
+        match self {
+            Version::V3_3 | Version::V1_3 => "BIC",
+            Version::V1_9 => "BICFI",
+        }
+    }
+}
+
+/// The newest pain.001 version we support among the SEPA data formats a bank announced.
+pub fn select_descriptor<'a, I>(formats: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    formats
+        .into_iter()
+        .filter_map(|format| Version::from_descriptor(format).map(|version| (version, format)))
+        .max_by_key(|(version, _)| *version)
+        .map(|(_, format)| format.as_str())
+}
+
+/// Generate a pain.001 document in the version of `descriptor` with all `transfers` debited
+/// from `debtor` in a single payment information block.
+///
+/// `batch_booking` asks the bank to book the transfers as one sum (`true`) or each on its own
+/// (`false`), the bank decides if it is `None`.
+pub fn generate(
+    descriptor: &str,
+    debtor: &SepaAccount,
+    debtor_name: &str,
+    transfers: &[Transfer],
+    batch_booking: Option<bool>,
+) -> Result<Vec<u8>> {
+    let version = Version::from_descriptor(descriptor)
+        .ok_or_else(|| se::Error::custom(format!("Unsupported pain.001 version {}", descriptor)))?;
+    if transfers.is_empty() {
+        return Err(se::Error::custom("No transfers given"));
+    }
+    for transfer in transfers {
+        if transfer.amount <= Decimal::ZERO || transfer.amount.round_dp(2) != transfer.amount {
+            return Err(se::Error::custom(format!(
+                "Invalid transfer amount {}",
+                transfer.amount
+            )));
+        }
+        check_text("recipient name", &transfer.recipient.name, 70)?;
+        check_text("purpose", &transfer.purpose, 140)?;
+        if let Some(reference) = &transfer.end_to_end_reference {
+            check_text("end-to-end reference", reference, 35)?;
+        }
+    }
+    check_text("debtor name", debtor_name, 70)?;
+
+    let message_id = new_message_id();
+    let count = transfers.len();
+    let control_sum: Decimal = transfers.iter().map(|transfer| transfer.amount).sum();
+    let created = Local::now().naive_local().format("%Y-%m-%dT%H:%M:%S");
+    // 1999-01-01 asks for execution as soon as possible.
+    let execution_date = NaiveDate::from_ymd_opt(1999, 1, 1).unwrap();
+    let bic = version.bic_element();
+
+    let mut xml = String::new();
+    // Writing to a String can't fail.
+    let _ = write!(
+        xml,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+         <Document xmlns=\"{namespace}\" \
+         xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+         xsi:schemaLocation=\"{namespace} {schema}.xsd\">\
+         <CstmrCdtTrfInitn>\
+         <GrpHdr>\
+         <MsgId>{message_id}</MsgId>\
+         <CreDtTm>{created}</CreDtTm>\
+         <NbOfTxs>{count}</NbOfTxs>\
+         <CtrlSum>{control_sum:.2}</CtrlSum>\
+         <InitgPty><Nm>{name}</Nm></InitgPty>\
+         </GrpHdr>\
+         <PmtInf>\
+         <PmtInfId>{message_id}</PmtInfId>\
+         <PmtMtd>TRF</PmtMtd>",
+        namespace = version.namespace(),
+        schema = version.namespace().rsplit(':').next().unwrap_or_default(),
+        name = escape(debtor_name),
+    );
+    if let Some(batch_booking) = batch_booking {
+        let _ = write!(xml, "<BtchBookg>{}</BtchBookg>", batch_booking);
+    }
+    let _ = write!(
+        xml,
+        "<NbOfTxs>{count}</NbOfTxs>\
+         <CtrlSum>{control_sum:.2}</CtrlSum>\
+         <PmtTpInf><SvcLvl><Cd>SEPA</Cd></SvcLvl></PmtTpInf>"
+    );
+    match version {
+        Version::V3_3 | Version::V1_3 => {
+            let _ = write!(xml, "<ReqdExctnDt>{}</ReqdExctnDt>", execution_date);
+        }
+        Version::V1_9 => {
+            let _ = write!(
+                xml,
+                "<ReqdExctnDt><Dt>{}</Dt></ReqdExctnDt>",
+                execution_date
+            );
+        }
+    }
+    let _ = write!(
+        xml,
+        "<Dbtr><Nm>{name}</Nm></Dbtr>\
+         <DbtrAcct><Id><IBAN>{iban}</IBAN></Id></DbtrAcct>",
+        name = escape(debtor_name),
+        iban = escape(&debtor.iban),
+    );
+    match &debtor.bic {
+        Some(debtor_bic) => {
+            let _ = write!(
+                xml,
+                "<DbtrAgt><FinInstnId><{bic}>{}</{bic}></FinInstnId></DbtrAgt>",
+                escape(debtor_bic),
+            );
+        }
+        None => xml.push_str(
+            "<DbtrAgt><FinInstnId><Othr><Id>NOTPROVIDED</Id></Othr></FinInstnId></DbtrAgt>",
+        ),
+    }
+    xml.push_str("<ChrgBr>SLEV</ChrgBr>");
+
+    for transfer in transfers {
+        let recipient = &transfer.recipient;
+        let _ = write!(
+            xml,
+            "<CdtTrfTxInf>\
+             <PmtId><EndToEndId>{}</EndToEndId></PmtId>\
+             <Amt><InstdAmt Ccy=\"EUR\">{:.2}</InstdAmt></Amt>",
+            escape(
+                transfer
+                    .end_to_end_reference
+                    .as_deref()
+                    .unwrap_or("NOTPROVIDED")
+            ),
+            transfer.amount,
+        );
+        if let Some(recipient_bic) = &recipient.bic {
+            let _ = write!(
+                xml,
+                "<CdtrAgt><FinInstnId><{bic}>{}</{bic}></FinInstnId></CdtrAgt>",
+                escape(recipient_bic),
+            );
+        }
+        let _ = write!(
+            xml,
+            "<Cdtr><Nm>{}</Nm></Cdtr>\
+             <CdtrAcct><Id><IBAN>{}</IBAN></Id></CdtrAcct>\
+             <RmtInf><Ustrd>{}</Ustrd></RmtInf>\
+             </CdtTrfTxInf>",
+            escape(&recipient.name),
+            escape(&recipient.iban),
+            escape(&transfer.purpose),
+        );
+    }
+    xml.push_str("</PmtInf></CstmrCdtTrfInitn></Document>");
+    Ok(xml.into_bytes())
+}
+
+/// Random id for the message and its payment information block.
+fn new_message_id() -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(20)
+        .map(char::from)
+        .collect()
+}
+
+/// Check that `text` has at most `max` characters, all from the SEPA Latin character set.
+fn check_text(field: &str, text: &str, max: usize) -> Result<()> {
+    if text.chars().count() > max {
+        return Err(se::Error::custom(format!(
+            "The {} is longer than {} characters",
+            field, max
+        )));
+    }
+    let is_sepa = |c: char| c.is_ascii_alphanumeric() || "/-?:().,'+ ".contains(c);
+    if let Some(c) = text.chars().find(|c| !is_sepa(*c)) {
+        return Err(se::Error::custom(format!(
+            "The {} contains {:?}, which is not in the SEPA character set",
+            field, c
+        )));
+    }
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transfer::Recipient;
+    use pretty_assertions::assert_eq;
+    use roxmltree::Document;
+    use std::str::FromStr;
+
+    fn debtor() -> SepaAccount {
+        SepaAccount {
+            iban: "DE00123456780001234567".to_string(),
+            bic: Some("TESTDEFFXXX".to_string()),
+            account_number: "1234567".to_string(),
+            sub_account: None,
+            country_code: "280".to_string(),
+            bank_code: 12345678,
+        }
+    }
+
+    fn transfer(amount: &str) -> Transfer {
+        Transfer {
+            recipient: Recipient {
+                name: "Max Mustermann + Co".to_string(),
+                iban: "DE00765432100007654321".to_string(),
+                bic: None,
+            },
+            amount: Decimal::from_str(amount).unwrap(),
+            purpose: "Rechnung 'A-42'".to_string(),
+            end_to_end_reference: None,
+        }
+    }
+
+    fn text<'a>(document: &'a Document, name: &str) -> Vec<&'a str> {
+        document
+            .descendants()
+            .filter(|node| node.tag_name().name() == name)
+            .filter_map(|node| node.text())
+            .collect()
+    }
+
+    #[test]
+    fn test_generate() {
+        let xml = generate(
+            "urn:iso:std:iso:20022:tech:xsd:pain.001.001.03",
+            &debtor(),
+            "Erika Mustermann",
+            &[transfer("12.5"), transfer("0.99")],
+            Some(false),
+        )
+        .unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        let document = Document::parse(&xml).unwrap();
+        assert_eq!(
+            document.root_element().tag_name().namespace(),
+            Some("urn:iso:std:iso:20022:tech:xsd:pain.001.001.03")
+        );
+        assert_eq!(text(&document, "BtchBookg"), vec!["false"]);
+        assert_eq!(text(&document, "NbOfTxs"), vec!["2", "2"]);
+        assert_eq!(text(&document, "CtrlSum"), vec!["13.49", "13.49"]);
+        assert_eq!(text(&document, "ReqdExctnDt"), vec!["1999-01-01"]);
+        assert_eq!(text(&document, "BIC"), vec!["TESTDEFFXXX"]);
+        assert_eq!(
+            text(&document, "IBAN"),
+            vec![
+                "DE00123456780001234567",
+                "DE00765432100007654321",
+                "DE00765432100007654321"
+            ]
+        );
+        assert_eq!(text(&document, "InstdAmt"), vec!["12.50", "0.99"]);
+        assert_eq!(text(&document, "Nm")[2], "Max Mustermann + Co");
+        assert_eq!(text(&document, "Ustrd")[0], "Rechnung 'A-42'");
+        assert_eq!(text(&document, "EndToEndId")[0], "NOTPROVIDED");
+
+        let xml = generate(
+            "urn:iso:std:iso:20022:tech:xsd:pain.001.001.09",
+            &debtor(),
+            "Erika Mustermann",
+            &[transfer("1")],
+            None,
+        )
+        .unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        let document = Document::parse(&xml).unwrap();
+        assert_eq!(text(&document, "Dt"), vec!["1999-01-01"]);
+        assert_eq!(text(&document, "BICFI"), vec!["TESTDEFFXXX"]);
+        assert!(text(&document, "BIC").is_empty());
+        assert!(text(&document, "BtchBookg").is_empty());
+    }
+
+    #[test]
+    fn test_generate_invalid() {
+        let descriptor = "urn:iso:std:iso:20022:tech:xsd:pain.001.001.03";
+        assert!(generate(descriptor, &debtor(), "Erika", &[], None).is_err());
+        assert!(generate(descriptor, &debtor(), "Erika", &[transfer("-1")], None).is_err());
+        assert!(generate(descriptor, &debtor(), "Erika", &[transfer("0.001")], None).is_err());
+        assert!(generate(
+            "sepade.pain.008.001.02.xsd",
+            &debtor(),
+            "Erika",
+            &[transfer("1")],
+            None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_generate_invalid_text() {
+        let descriptor = "urn:iso:std:iso:20022:tech:xsd:pain.001.001.03";
+        let generate = |debtor_name: &str, transfer: Transfer| {
+            generate(descriptor, &debtor(), debtor_name, &[transfer], None)
+                .map_err(|err| err.to_string())
+        };
+        let name = "a".repeat(70);
+        let purpose = "a".repeat(140);
+        let reference = "a".repeat(35);
+
+        let mut valid = transfer("1");
+        valid.recipient.name = name.clone();
+        valid.purpose = purpose.clone();
+        valid.end_to_end_reference = Some(reference.clone());
+        assert!(generate(&name, valid.clone()).is_ok());
+
+        assert_eq!(
+            generate(&format!("{}a", name), valid.clone()),
+            Err("The debtor name is longer than 70 characters".to_string())
+        );
+        let mut long_name = valid.clone();
+        long_name.recipient.name.push('a');
+        assert_eq!(
+            generate("Erika", long_name),
+            Err("The recipient name is longer than 70 characters".to_string())
+        );
+        let mut long_purpose = valid.clone();
+        long_purpose.purpose.push('a');
+        assert_eq!(
+            generate("Erika", long_purpose),
+            Err("The purpose is longer than 140 characters".to_string())
+        );
+        let mut long_reference = valid.clone();
+        long_reference.end_to_end_reference = Some(format!("{}a", reference));
+        assert_eq!(
+            generate("Erika", long_reference),
+            Err("The end-to-end reference is longer than 35 characters".to_string())
+        );
+
+        assert_eq!(
+            generate("Erika & Max", valid.clone()),
+            Err("The debtor name contains '&', which is not in the SEPA character set".to_string())
+        );
+        let mut invalid_purpose = valid;
+        invalid_purpose.purpose = "Rechnung <42>".to_string();
+        assert_eq!(
+            generate("Erika", invalid_purpose),
+            Err("The purpose contains '<', which is not in the SEPA character set".to_string())
+        );
+    }
+
+    #[test]
+    fn test_select_descriptor() {
+        let formats = vec![
+            "urn:iso:std:iso:20022:tech:xsd:pain.001.003.03".to_string(),
+            "urn:iso:std:iso:20022:tech:xsd:pain.001.001.09".to_string(),
+            "urn:iso:std:iso:20022:tech:xsd:pain.001.001.03".to_string(),
+            "urn:iso:std:iso:20022:tech:xsd:pain.008.001.02".to_string(),
+        ];
+        assert_eq!(
+            select_descriptor(&formats),
+            Some("urn:iso:std:iso:20022:tech:xsd:pain.001.001.09")
+        );
+        assert_eq!(select_descriptor(&formats[3..]), None);
+    }
+}
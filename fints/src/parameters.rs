@@ -0,0 +1,368 @@
+use crate::data_types::*;
+use crate::de::{self, RawSegment};
+use crate::segments::*;
+use crate::state::ParameterData;
+use serde::de::Error as _;
+
+/// Bank parameter data (BPD).
+///
+/// Besides the general parameters, the BPD contain a parameter segment for every job and
+/// version the bank supports, e.g. HISALS for HKSAL.
+#[derive(Debug)]
+pub struct BankParameters {
+    pub version: u16,
+
+    pub bank_name: String,
+
+    /// Maximum number of jobs per message.
+    pub max_jobs_per_message: u16,
+
+    pub hbci_versions: Vec<u16>,
+
+    /// Communication addresses, usually only the URL of the PIN/TAN endpoint.
+    pub communication_parameters: Vec<DEG_CommunicationParameters>,
+
+    pub security_methods: Vec<DEG_SecurityMethods>,
+
+    /// PIN/TAN specific information including which jobs need a TAN.
+    pub pin_tan_information: Option<DEG_PinTanInformation>,
+
+    /// Parameter segments of the supported jobs.
+    pub job_parameters: Vec<RawSegment>,
+}
+
+impl BankParameters {
+    pub fn from_parameter_data(bpd: &ParameterData) -> Result<BankParameters, de::Error> {
+        let mut bank_parameters: Option<Seg_HIBPA_BankParameters> = None;
+        let mut communication_parameters = vec![];
+        let mut security_methods = vec![];
+        let mut pin_tan_information = None;
+        let mut job_parameters = vec![];
+        for segment in &bpd.segments {
+            match segment.identifier() {
+                Seg_HIBPA_BankParameters::ID => bank_parameters = Some(segment.deserialize()?),
+                Seg_HIKOM_CommunicationAccess::ID => {
+                    let access: Seg_HIKOM_CommunicationAccess = segment.deserialize()?;
+                    communication_parameters.extend(access.communication_parameters);
+                }
+                Seg_HISHV_SecurityMethods::ID => {
+                    let methods: Seg_HISHV_SecurityMethods = segment.deserialize()?;
+                    security_methods.extend(methods.security_methods);
+                }
+                Seg_HIPINS_PinTanInformation::ID => {
+                    let information: Seg_HIPINS_PinTanInformation = segment.deserialize()?;
+                    pin_tan_information = Some(information.pin_tan_information);
+                }
+                _ => job_parameters.push(segment.clone()),
+            }
+        }
+        let bank_parameters =
+            bank_parameters.ok_or_else(|| de::Error::custom("BPD without HIBPA segment"))?;
+
+        Ok(BankParameters {
+            version: bank_parameters.bpd_version,
+            bank_name: bank_parameters.bank_name,
+            max_jobs_per_message: bank_parameters.max_jobs_per_message,
+            hbci_versions: bank_parameters.supported_hbci_versions.versions,
+            communication_parameters,
+            security_methods,
+            pin_tan_information,
+            job_parameters,
+        })
+    }
+
+    /// Parameter segments of a job such as `HKSAL`, one for each supported version.
+    pub fn job_parameters<'a>(&'a self, job: &str) -> impl Iterator<Item = &'a RawSegment> {
+        let identifier = parameter_segment_identifier(job);
+        self.job_parameters
+            .iter()
+            .filter(move |segment| segment.identifier() == identifier)
+    }
+
+    /// Versions of a job such as `HKSAL` supported by the bank in ascending order. This is
+    /// empty if the job isn't supported at all.
+    pub fn supported_versions(&self, job: &str) -> Vec<u16> {
+        let mut versions: Vec<u16> = self
+            .job_parameters(job)
+            .filter_map(|segment| segment.version())
+            .collect();
+        versions.sort_unstable();
+        versions.dedup();
+        versions
+    }
+
+    /// Highest version of a job supported by both the bank and us.
+    pub fn highest_common_version(&self, job: &str, our_versions: &[u16]) -> Option<u16> {
+        self.supported_versions(job)
+            .into_iter()
+            .rev()
+            .find(|version| our_versions.contains(version))
+    }
+
+    /// Whether a job such as `HKSAL` needs a TAN. Jobs not listed in HIPINS don't.
+    pub fn is_tan_required(&self, job: &str) -> bool {
+        self.pin_tan_information
+            .as_ref()
+            .is_some_and(|information| {
+                information
+                    .jobs
+                    .iter()
+                    .any(|j| j.segment_identifier == job && j.tan_required)
+            })
+    }
+
+    /// SEPA data formats the bank accepts, e.g.
+    /// `urn:iso:std:iso:20022:tech:xsd:pain.001.001.03`, as announced in HISPAS.
+    pub fn supported_sepa_formats(&self) -> Result<Vec<String>, de::Error> {
+        let mut formats = vec![];
+        for segment in self.job_parameters(Seg_HKSPA_SepaAccountInfo1::ID) {
+            match segment.version() {
+                Some(1) => {
+                    let parameters: Seg_HISPAS_SepaAccountParameters1 = segment.deserialize()?;
+                    formats.extend(parameters.parameters.supported_sepa_formats);
+                }
+                Some(2) => {
+                    let parameters: Seg_HISPAS_SepaAccountParameters2 = segment.deserialize()?;
+                    formats.extend(parameters.parameters.supported_sepa_formats);
+                }
+                Some(3) => {
+                    let parameters: Seg_HISPAS_SepaAccountParameters3 = segment.deserialize()?;
+                    formats.extend(parameters.parameters.supported_sepa_formats);
+                }
+                _ => {}
+            }
+        }
+        formats.sort();
+        formats.dedup();
+        Ok(formats)
+    }
+}
+
+/// User parameter data (UPD).
+#[derive(Debug)]
+pub struct UserParameters {
+    pub version: u16,
+
+    pub user_id: String,
+
+    /// Whether jobs not listed for an account are blocked.
+    pub usage: UpdUsage,
+
+    pub username: Option<String>,
+
+    /// The accounts the user may access.
+    pub accounts: Vec<Account>,
+}
+
+impl UserParameters {
+    pub fn from_parameter_data(upd: &ParameterData) -> Result<UserParameters, de::Error> {
+        let mut user_parameters: Option<Seg_HIUPA_UserParameters> = None;
+        let mut accounts = vec![];
+        for segment in &upd.segments {
+            match segment.identifier() {
+                Seg_HIUPA_UserParameters::ID => user_parameters = Some(segment.deserialize()?),
+                Seg_HIUPD_AccountInformation::ID => {
+                    let account: Seg_HIUPD_AccountInformation = segment.deserialize()?;
+                    accounts.push(Account::from(account));
+                }
+                _ => {}
+            }
+        }
+        let user_parameters =
+            user_parameters.ok_or_else(|| de::Error::custom("UPD without HIUPA segment"))?;
+
+        Ok(UserParameters {
+            version: user_parameters.upd_version,
+            user_id: user_parameters.user_id,
+            usage: user_parameters.upd_usage,
+            username: user_parameters.username,
+            accounts,
+        })
+    }
+}
+
+/// An account as listed in the UPD.
+#[derive(Debug)]
+pub struct Account {
+    pub account_number: Option<String>,
+
+    pub sub_account: Option<String>,
+
+    pub bank_code: Option<u32>,
+
+    pub iban: Option<String>,
+
+    pub customer_id: String,
+
+    /// Kind of account, e.g. 1 to 9 for current accounts and 10 to 19 for savings accounts.
+    pub account_type: Option<u16>,
+
+    pub currency: Option<String>,
+
+    pub holder_name: String,
+
+    pub product_name: Option<String>,
+
+    pub limit: Option<DEG_AccountLimit>,
+
+    /// Jobs allowed for this account.
+    pub allowed_jobs: Vec<DEG_AllowedJob>,
+}
+
+impl From<Seg_HIUPD_AccountInformation> for Account {
+    fn from(account: Seg_HIUPD_AccountInformation) -> Self {
+        let (account_number, sub_account, bank_code) = match account.account_connection {
+            Some(connection) => (
+                Some(connection.account_number),
+                connection.sub_account,
+                Some(connection.institute_identifier.bank_code),
+            ),
+            None => (None, None, None),
+        };
+        let holder_name = match account.holder_name_2 {
+            Some(holder_name_2) => format!("{} {}", account.holder_name_1, holder_name_2),
+            None => account.holder_name_1,
+        };
+        Account {
+            account_number,
+            sub_account,
+            bank_code,
+            iban: account.iban,
+            customer_id: account.customer_id,
+            account_type: account.account_type,
+            currency: account.currency,
+            holder_name,
+            product_name: account.product_name,
+            limit: account.limit,
+            allowed_jobs: account.allowed_jobs,
+        }
+    }
+}
+
+/// The parameter segment of a job is named after the job, e.g. HISALS for HKSAL.
+fn parameter_segment_identifier(job: &str) -> String {
+    format!("HI{}S", job.get(2..).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::de::RawMessage;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_bank_parameters() {
+        let response = RawMessage::from_bytes(
+            b"HIBPA:4:3:4+12+280:12345678+Testbank+3+1:2+220:300+10000'\
+              HIKOM:5:4:4+280:12345678+1+3:https?://example.com/fints'\
+              HISHV:6:3:4+N+PIN:1+RDH:5:10'\
+              HIPINS:7:1:4+1+1+0+5:20:6:Benutzerkennung::HKSAL:N:HKCCS:J'\
+              HISALS:8:5:4+1+1+0'\
+              HISALS:9:7:4+1+1+0'\
+              HISPAS:10:1:4+1+1+0+J:N:N:urn?:iso?:std?:iso?:20022?:tech?:xsd?:pain.001.001.03'\
+              HISPAS:11:2:4+1+1+0+J:N:N:J:35:urn?:iso?:std?:iso?:20022?:tech?:xsd?:pain.001.001.09\
+              :urn?:iso?:std?:iso?:20022?:tech?:xsd?:pain.001.001.03'\
+              HIUPA:10:4:4+test1+4+0'",
+        )
+        .unwrap();
+        let bpd = ParameterData {
+            version: 12,
+            segments: response
+                .segments
+                .into_iter()
+                .filter(|s| s.identifier() != "HIUPA")
+                .collect(),
+        };
+        let bank_parameters = BankParameters::from_parameter_data(&bpd).unwrap();
+
+        assert_eq!(bank_parameters.version, 12);
+        assert_eq!(bank_parameters.bank_name, "Testbank");
+        assert_eq!(bank_parameters.max_jobs_per_message, 3);
+        assert_eq!(bank_parameters.hbci_versions, vec![220, 300]);
+        assert_eq!(
+            bank_parameters.communication_parameters[0].address,
+            "https://example.com/fints"
+        );
+        assert_eq!(bank_parameters.security_methods.len(), 2);
+        assert_eq!(bank_parameters.security_methods[1].versions, vec![5, 10]);
+
+        let pin_tan_information = bank_parameters.pin_tan_information.as_ref().unwrap();
+        assert_eq!(pin_tan_information.max_pin_length, Some(20));
+        assert_eq!(
+            pin_tan_information.user_id_text.as_deref(),
+            Some("Benutzerkennung")
+        );
+        assert_eq!(pin_tan_information.jobs.len(), 2);
+
+        assert_eq!(bank_parameters.supported_versions("HKSAL"), vec![5, 7]);
+        assert!(bank_parameters.supported_versions("HKKAZ").is_empty());
+        assert_eq!(
+            bank_parameters.highest_common_version("HKSAL", &[5, 6]),
+            Some(5)
+        );
+        assert_eq!(bank_parameters.highest_common_version("HKSAL", &[6]), None);
+        assert!(!bank_parameters.is_tan_required("HKSAL"));
+        assert!(bank_parameters.is_tan_required("HKCCS"));
+        assert!(!bank_parameters.is_tan_required("HKKAZ"));
+        assert_eq!(
+            bank_parameters.supported_sepa_formats().unwrap(),
+            vec![
+                "urn:iso:std:iso:20022:tech:xsd:pain.001.001.03",
+                "urn:iso:std:iso:20022:tech:xsd:pain.001.001.09",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_user_parameters() {
+        let response = RawMessage::from_bytes(
+            b"HIUPA:4:4:4+test1+4+0+Max Mustermann'\
+              HIUPD:5:6:4+1234567::280:12345678+DE00123456780001234567+test1+1+EUR+Max+Mustermann\
+              +Girokonto+T:1000,50:EUR+HKSAL:1+HKCCS:1:E:500,:EUR'\
+              HIUPD:6:6:4++DE00123456780007654321+test1++EUR+Max'",
+        )
+        .unwrap();
+        let upd = ParameterData {
+            version: 4,
+            segments: response.segments,
+        };
+        let user_parameters = UserParameters::from_parameter_data(&upd).unwrap();
+        assert_eq!(user_parameters.version, 4);
+        assert_eq!(user_parameters.user_id, "test1");
+        assert_eq!(user_parameters.username.as_deref(), Some("Max Mustermann"));
+        assert_eq!(user_parameters.accounts.len(), 2);
+
+        let account = &user_parameters.accounts[0];
+        assert_eq!(account.account_number.as_deref(), Some("1234567"));
+        assert_eq!(account.sub_account, None);
+        assert_eq!(account.bank_code, Some(12345678));
+        assert_eq!(account.iban.as_deref(), Some("DE00123456780001234567"));
+        assert_eq!(account.account_type, Some(1));
+        assert_eq!(account.holder_name, "Max Mustermann");
+        assert_eq!(account.product_name.as_deref(), Some("Girokonto"));
+        let limit = account.limit.as_ref().unwrap().amount.as_ref().unwrap();
+        assert_eq!(limit.value.to_string(), "1000.50");
+        assert_eq!(account.allowed_jobs.len(), 2);
+        assert_eq!(account.allowed_jobs[1].job, "HKCCS");
+        assert_eq!(
+            account.allowed_jobs[1]
+                .amount
+                .as_ref()
+                .unwrap()
+                .value
+                .to_string(),
+            "500"
+        );
+
+        let account = &user_parameters.accounts[1];
+        assert_eq!(account.account_number, None);
+        assert_eq!(account.iban.as_deref(), Some("DE00123456780007654321"));
+        assert!(account.allowed_jobs.is_empty());
+    }
+
+    #[test]
+    fn test_bank_parameters_without_hibpa() {
+        let bpd = ParameterData::default();
+        assert!(BankParameters::from_parameter_data(&bpd).is_err());
+    }
+}
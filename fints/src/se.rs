@@ -1,9 +1,10 @@
 //! Serialization.
 
-use log::{debug, info, trace};
+use crate::utils::{escape_fints, StructKind};
+use encoding_rs::ISO_8859_15;
+use log::{info, trace};
 use serde::ser::{self, Serialize};
 use std::fmt::{self, Debug, Display};
-use std::str;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Error(String);
@@ -17,49 +18,98 @@ impl ser::Error for Error {
 
 impl Display for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str(std::error::Error::description(self))
+        formatter.write_str(&self.0)
     }
 }
 
-impl std::error::Error for Error {
-    fn description(&self) -> &str {
-        match self {
-            Error(ref msg) => msg,
-        }
-    }
-}
+impl std::error::Error for Error {}
 
 pub struct Serializer {
-    /// The final serialized output string.
-    output: String,
+    /// The final serialized output.
+    /// Text is ISO 8859-15 encoded as required by FinTS, binary data is written as-is.
+    output: Vec<u8>,
 
-    /// Keep track of whether we're currently inside a DEG or not.
-    /// This is necessary because DEGs are delmited differently than DEs.
-    inside_deg: bool,
+    /// Stack of the structs we're currently inside of.
+    /// The innermost struct decides how its fields are delimited: Segments delimit their DEs
+    /// using `+`, DEGs delimit their components using `:` and messages don't delimit their
+    /// segments at all.
+    struct_stack: Vec<Struct>,
 
-    /// The current field's index in the current struct.
-    field_index_in_struct: u32,
+    /// Delimiters that haven't been written yet.
+    /// They are only written once a non-empty value follows so that trailing empty DEs and
+    /// DEG components are left out as required by the formatting rules.
+    pending_delimiters: String,
 
-    /// Total size of the last struct.
-    last_struct_size: usize,
+    /// Stack of repeated DEs, DEGs or segments that are currently being serialized.
+    repetitions: Vec<Repetition>,
 
-    /// Stack of struct names.
-    /// We need this to be able to correctly count recursively nested structs.
-    struct_stack: Vec<String>,
+    /// For pretty printing the Serialization tree.
+    tree_builder: ptree::TreeBuilder,
+}
 
-    /// Total number of elements in the current segment.
-    /// This is set to the size of the struct every time we pass a new segment.
-    /// This is important to know so we know when a segment ends.
-    current_segment_elements_count: usize,
+/// A struct that is currently being serialized.
+struct Struct {
+    kind: StructKind,
 
-    /// The element position inside the current segment.
-    current_segment_index: u32,
+    /// Number of fields serialized so far.
+    fields: usize,
+}
 
-    /// For pretty printing the Serialization tree.
-    tree_builder: ptree::TreeBuilder,
+/// State of a list of repeated elements.
+struct Repetition {
+    /// Delimiter between repetitions. Repeated segments aren't delimited.
+    delimiter: Option<char>,
+
+    /// Number of repetitions serialized so far.
+    count: usize,
 }
 
-pub fn to_string<T>(value: &T) -> Result<String>
+/// Serialize a list of DEs or DEGs that may be repeated at most `max` times.
+///
+/// Unused repetitions are written as empty elements so that whatever follows the list stays in
+/// its place. If the list is the last element, these are trimmed like any other trailing empty
+/// element.
+pub struct Repeated<'a, T> {
+    values: &'a [T],
+    max: usize,
+}
+
+impl<'a, T> Repeated<'a, T> {
+    pub fn new(values: &'a [T], max: usize) -> Self {
+        Repeated { values, max }
+    }
+}
+
+impl<T> Serialize for Repeated<'_, T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        use serde::ser::SerializeTuple;
+
+        if self.values.len() > self.max {
+            return Err(ser::Error::custom(format!(
+                "At most {} repetitions allowed but got {}",
+                self.max,
+                self.values.len()
+            )));
+        }
+        let mut tuple = serializer.serialize_tuple(self.max)?;
+        for value in self.values {
+            tuple.serialize_element(value)?;
+        }
+        for _ in self.values.len()..self.max {
+            tuple.serialize_element(&None::<&T>)?;
+        }
+        tuple.end()
+    }
+}
+
+/// Serialize `value` to its FinTS wire representation.
+pub fn to_bytes<T>(value: &T) -> Result<Vec<u8>>
 where
     T: Serialize + Debug,
 {
@@ -70,13 +120,10 @@ where
     trace!("\n{:#?}", value);
 
     let mut serializer = Serializer {
-        output: String::new(),
-        inside_deg: false,
-        field_index_in_struct: 0,
-        last_struct_size: 0,
+        output: vec![],
         struct_stack: vec![],
-        current_segment_elements_count: 0,
-        current_segment_index: 0,
+        pending_delimiters: String::new(),
+        repetitions: vec![],
         tree_builder: ptree::TreeBuilder::new("\nSerialize".to_string()),
     };
     value.serialize(&mut serializer)?;
@@ -89,7 +136,91 @@ where
     Ok(serializer.output)
 }
 
-impl<'a> ser::Serializer for &'a mut Serializer {
+/// Serialize `value` and decode the result for display purposes.
+/// Binary data elements are not preserved faithfully, use `to_bytes` for sending.
+pub fn to_string<T>(value: &T) -> Result<String>
+where
+    T: Serialize + Debug,
+{
+    let bytes = to_bytes(value)?;
+    Ok(ISO_8859_15
+        .decode_without_bom_handling(&bytes)
+        .0
+        .into_owned())
+}
+
+/// Error for serde data model types that have no FinTS representation.
+fn unsupported(what: &str) -> Error {
+    Error(format!("Serializing {} is not supported", what))
+}
+
+impl Serializer {
+    /// Write text to the output using the FinTS character set.
+    fn write(&mut self, s: &str) -> Result<()> {
+        let (encoded, _, had_errors) = ISO_8859_15.encode(s);
+        if had_errors {
+            // Only name the offending character, the text might be a PIN.
+            let unmappable = s
+                .chars()
+                .find(|c| ISO_8859_15.encode(&c.to_string()).2)
+                .unwrap_or(char::REPLACEMENT_CHARACTER);
+            return Err(ser::Error::custom(format!(
+                "{:?} can't be encoded in ISO 8859-15",
+                unmappable
+            )));
+        }
+        self.output.extend_from_slice(&encoded);
+        Ok(())
+    }
+
+    /// Write a value preceded by all delimiters that are still pending.
+    /// Empty values don't flush the delimiters since they might turn out to be trailing.
+    fn write_value(&mut self, s: &str) -> Result<()> {
+        if s.is_empty() {
+            return Ok(());
+        }
+        let delimiters = std::mem::take(&mut self.pending_delimiters);
+        self.write(&delimiters)?;
+        self.write(s)
+    }
+
+    /// The delimiter between the fields of the innermost struct.
+    fn delimiter(&self) -> Option<char> {
+        match self.struct_stack.last().map(|s| s.kind) {
+            Some(StructKind::Message) => None,
+            Some(StructKind::Segment) => Some('+'),
+            Some(StructKind::DataElementGroup) | None => Some(':'),
+        }
+    }
+
+    /// Start a list of repeated elements at the current position.
+    fn begin_repetitions(&mut self) {
+        // Repeated elements are delimited the same way as the fields around them.
+        let delimiter = self.delimiter();
+        self.repetitions.push(Repetition {
+            delimiter,
+            count: 0,
+        });
+    }
+
+    /// Serialize the next repetition of the innermost list.
+    fn serialize_repetition<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let repetition = self
+            .repetitions
+            .last_mut()
+            .ok_or_else(|| Error("Repetition outside of a list".to_string()))?;
+        if let (Some(delimiter), true) = (repetition.delimiter, repetition.count > 0) {
+            self.pending_delimiters.push(delimiter);
+        }
+        repetition.count += 1;
+        value.serialize(&mut *self)
+    }
+}
+
+impl ser::Serializer for &mut Serializer {
     type Ok = ();
     type Error = Error;
     type SerializeSeq = Self;
@@ -100,70 +231,62 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     type SerializeStruct = Self;
     type SerializeStructVariant = Self;
 
-    fn serialize_bool(self, _v: bool) -> Result<()> {
-        unimplemented!();
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.write_value(if v { "J" } else { "N" })
     }
 
     fn serialize_i8(self, v: i8) -> Result<()> {
-        self.output += &v.to_string();
-        Ok(())
+        self.write_value(&v.to_string())
     }
 
     fn serialize_i16(self, v: i16) -> Result<()> {
-        self.output += &v.to_string();
-        Ok(())
+        self.write_value(&v.to_string())
     }
 
     fn serialize_i32(self, v: i32) -> Result<()> {
-        self.output += &v.to_string();
-        Ok(())
+        self.write_value(&v.to_string())
     }
 
     fn serialize_i64(self, v: i64) -> Result<()> {
-        self.output += &v.to_string();
-        Ok(())
+        self.write_value(&v.to_string())
     }
 
     fn serialize_u8(self, v: u8) -> Result<()> {
-        self.output += &v.to_string();
-        Ok(())
+        self.write_value(&v.to_string())
     }
 
     fn serialize_u16(self, v: u16) -> Result<()> {
-        self.output += &v.to_string();
-        Ok(())
+        self.write_value(&v.to_string())
     }
 
     fn serialize_u32(self, v: u32) -> Result<()> {
-        self.output += &v.to_string();
-        Ok(())
+        self.write_value(&v.to_string())
     }
 
     fn serialize_u64(self, v: u64) -> Result<()> {
-        self.output += &v.to_string();
-        Ok(())
+        self.write_value(&v.to_string())
     }
 
     fn serialize_f32(self, _v: f32) -> Result<()> {
-        unimplemented!();
+        Err(unsupported("f32"))
     }
 
     fn serialize_f64(self, _v: f64) -> Result<()> {
-        unimplemented!()
+        Err(unsupported("f64"))
     }
 
     fn serialize_char(self, v: char) -> Result<()> {
-        self.output += &v.to_string();
-        Ok(())
+        self.write_value(&v.to_string())
     }
 
     fn serialize_str(self, v: &str) -> Result<()> {
-        self.output += v;
-        Ok(())
+        self.write_value(&escape_fints(v))
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<()> {
-        self.output += str::from_utf8(v).map_err(|x| Error(x.to_string()))?;
+        // Binary data is prefixed with its length and not escaped.
+        self.write_value(&format!("@{}@", v.len()))?;
+        self.output.extend_from_slice(v);
         Ok(())
     }
 
@@ -180,11 +303,11 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_unit(self) -> Result<()> {
-        unimplemented!()
+        Err(unsupported("unit"))
     }
 
     fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
-        unimplemented!()
+        Err(unsupported("unit structs"))
     }
 
     fn serialize_unit_variant(
@@ -201,7 +324,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        unimplemented!()
+        Err(unsupported("newtype structs"))
     }
 
     fn serialize_newtype_variant<T>(
@@ -214,15 +337,17 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        unimplemented!()
+        Err(unsupported("newtype variants"))
     }
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
-        unimplemented!()
+        self.begin_repetitions();
+        Ok(self)
     }
 
     fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
-        unimplemented!()
+        self.begin_repetitions();
+        Ok(self)
     }
 
     fn serialize_tuple_struct(
@@ -230,7 +355,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         _name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleStruct> {
-        unimplemented!()
+        Err(unsupported("tuple structs"))
     }
 
     fn serialize_tuple_variant(
@@ -240,40 +365,22 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         _variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        unimplemented!()
+        Err(unsupported("tuple variants"))
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
-        unimplemented!()
-    }
-
-    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
-        self.field_index_in_struct = 0;
-        self.last_struct_size = len;
-        self.struct_stack.push(name.to_string());
-        if name.starts_with("Msg") {
-            self.tree_builder.begin_child(format!("Message {}", name));
-        } else if name.starts_with("Seg") {
-            self.tree_builder.begin_child(format!("Segment {}", name));
-        } else if name.starts_with("DEG") {
-            self.tree_builder.begin_child(format!("DEG {}", name));
-        };
-
-        // If this is a new segment, set the length so that we also know when we're finished with
-        // this particular segment.
-        if name.starts_with("Seg") {
-            self.current_segment_elements_count = len;
-            self.current_segment_index = 0;
-        }
-
-        // Keep track of when a DEG starts since DEs inside of a DEG are delimited using `:`.
-        // Outside of oa DEG, DEs are delimited using `+`.
-        if name.starts_with("DEG") {
-            self.inside_deg = true;
-        } else {
-            self.inside_deg = false;
-        }
-
+        Err(unsupported("maps"))
+    }
+
+    fn serialize_struct(self, name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        let kind = StructKind::from_name(name).ok_or_else(|| {
+            Error(format!(
+                "Can't serialize struct {}, derive Message, Segment or DataElementGroup",
+                name
+            ))
+        })?;
+        self.tree_builder.begin_child(format!("{:?}", kind));
+        self.struct_stack.push(Struct { kind, fields: 0 });
         Ok(self)
     }
 
@@ -284,11 +391,11 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         _variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        unimplemented!()
+        Err(unsupported("struct variants"))
     }
 }
 
-impl<'a> ser::SerializeStruct for &'a mut Serializer {
+impl ser::SerializeStruct for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -296,83 +403,80 @@ impl<'a> ser::SerializeStruct for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        // If the parent struct containing this field is a segment, we'll increase the current
-        // segment counter.
-        if let Some(last) = self.struct_stack.last() {
-            if last.starts_with("Seg") {
-                self.current_segment_index += 1;
-
-                // If the parent is a Segment, we're not in a DEG.
-                // This will also reset inside_deg when ascending from a DEG.
-                // This will be set to true every time we descend into a struct
-                // whose name starts with DEG (see `serialize_struct`)
-                self.inside_deg = false;
-            }
-        }
-
-        // Do not separate segments using delimiters.
-        if self.struct_stack.iter().any(|x| x.starts_with("Seg")) {
-            if self.field_index_in_struct != 0 {
-                if self.inside_deg {
-                    self.output += ":";
-                } else {
-                    self.output += "+";
-                }
+        let delimiter = self.delimiter();
+        if let Some(current) = self.struct_stack.last_mut() {
+            // The first field of a DEG is delimited by whatever contains the DEG. That's a
+            // segment for top-level DEGs and the surrounding DEG for nested ones.
+            if let (Some(delimiter), true) = (delimiter, current.fields > 0) {
+                self.pending_delimiters.push(delimiter);
             }
+            current.fields += 1;
         }
-        self.field_index_in_struct += 1;
         self.tree_builder.add_empty_child(format!("DE {}", key));
         value.serialize(&mut **self)
     }
 
     fn end(self) -> Result<()> {
-        // In case this is a segment, we have to terminate it with `'`.
-        if let Some(last) = self.struct_stack.last() {
-            if last.starts_with("Seg") && !self.output.is_empty() {
-                self.output += "'\n";
+        // This marks the end of a parsed struct so we have to pop it from the stack.
+        let ended = self.struct_stack.pop().map(|s| s.kind);
+        let parent = self.struct_stack.last().map(|s| s.kind);
+
+        if ended == Some(StructKind::Segment) {
+            // Whatever is still pending at the end of a segment is trailing and can go. The
+            // segment then has to be terminated with `'`.
+            self.pending_delimiters.clear();
+            if !self.output.is_empty() {
+                self.write("'")?;
+            }
+        } else if ended == Some(StructKind::DataElementGroup)
+            && parent != Some(StructKind::DataElementGroup)
+        {
+            // At the end of a top-level DEG, trailing empty components can go as well.
+            // Nested DEGs are left alone since their components are continued by the
+            // surrounding DEG.
+            while self.pending_delimiters.ends_with(':') {
+                self.pending_delimiters.pop();
             }
         }
-
-        // This marks the end of a parsed struct so we have to pop it from the stack at the very
-        // end.
-        self.struct_stack.pop();
         self.tree_builder.end_child();
 
         Ok(())
     }
 }
 
-impl<'a> ser::SerializeSeq for &'a mut Serializer {
+impl ser::SerializeSeq for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_element<T>(&mut self, _value: &T) -> Result<()>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        unimplemented!()
+        self.serialize_repetition(value)
     }
     fn end(self) -> Result<()> {
-        unimplemented!()
+        self.repetitions.pop();
+        Ok(())
     }
 }
 
-impl<'a> ser::SerializeTuple for &'a mut Serializer {
+impl ser::SerializeTuple for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_element<T>(&mut self, _value: &T) -> Result<()>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        unimplemented!()
+        self.serialize_repetition(value)
     }
     fn end(self) -> Result<()> {
-        unimplemented!()
+        self.repetitions.pop();
+        Ok(())
     }
 }
 
-impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
+impl ser::SerializeTupleStruct for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -380,14 +484,14 @@ impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        unimplemented!()
+        Err(unsupported("tuple structs"))
     }
     fn end(self) -> Result<()> {
-        unimplemented!()
+        Err(unsupported("tuple structs"))
     }
 }
 
-impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
+impl ser::SerializeTupleVariant for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -395,14 +499,14 @@ impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        unimplemented!()
+        Err(unsupported("tuple variants"))
     }
     fn end(self) -> Result<()> {
-        unimplemented!()
+        Err(unsupported("tuple variants"))
     }
 }
 
-impl<'a> ser::SerializeMap for &'a mut Serializer {
+impl ser::SerializeMap for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -410,20 +514,20 @@ impl<'a> ser::SerializeMap for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        unimplemented!()
+        Err(unsupported("maps"))
     }
     fn serialize_value<T>(&mut self, _value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        unimplemented!()
+        Err(unsupported("maps"))
     }
     fn end(self) -> Result<()> {
-        unimplemented!()
+        Err(unsupported("maps"))
     }
 }
 
-impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
+impl ser::SerializeStructVariant for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -431,9 +535,318 @@ impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        unimplemented!()
+        Err(unsupported("struct variants"))
     }
     fn end(self) -> Result<()> {
-        unimplemented!()
+        Err(unsupported("struct variants"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_types::*;
+    use crate::de::from_bytes;
+    use crate::segments::*;
+    use chrono::{NaiveDate, NaiveTime};
+    use fints_derive::{DataElementGroup, Segment};
+    use pretty_assertions::assert_eq;
+
+    #[allow(non_camel_case_types)]
+    #[derive(Debug, DataElementGroup)]
+    struct DEG_Languages {
+        languages: Vec<u16>,
+    }
+
+    #[allow(non_camel_case_types)]
+    #[derive(Debug, Segment)]
+    #[fints(id = "HIXYZ", version = 1)]
+    struct Seg_HIXYZ_Repeated {
+        segment_head: DEG_SegmentHead,
+        #[fints(max = 3)]
+        versions: Vec<u16>,
+        languages: DEG_Languages,
+        institutes: Vec<DEG_InstituteIdentifier>,
+    }
+
+    #[test]
+    fn test_serialize_hkidn() {
+        let identification = Seg_HKIDN_Identification {
+            segment_head: Seg_HKIDN_Identification::new_segment_head(3),
+            institute_identifier: DEG_InstituteIdentifier {
+                country_code: "280".to_string(),
+                bank_code: 12345678,
+            },
+            customer_id: "test1".to_string(),
+            customer_system_id: "0".to_string(),
+            customer_system_status: CustomerSystemStatus::Required,
+        };
+        assert_eq!(
+            to_string(&identification).unwrap(),
+            "HKIDN:3:2+280:12345678+test1+0+1'"
+        );
+    }
+
+    #[test]
+    fn test_serialize_hnshk() {
+        let signature_head = Seg_HNSHK_SignatureHead {
+            segment_head: Seg_HNSHK_SignatureHead::new_segment_head(2),
+            security_profile: DEG_SecurityProfile {
+                security_method_code: SecurityMethodCode::PIN,
+                version: 1,
+            },
+            security_function: SecurityFunction::SingleStepAuth,
+            security_reference: "1234567".to_string(),
+            security_area: SecurityArea::SHM,
+            security_role: SecurityRole::ISS,
+            security_identification_details: DEG_SecurityIdentificationDetails {
+                security_party_identifier: SecurityPartyIdentifier::MS,
+                cardholder_identification: None,
+                party_identifier: Some("0".to_string()),
+            },
+            security_ref_no: 1,
+            security_date: DEG_SecurityDate {
+                date_identifier: DateIdentifier::STS,
+                date: NaiveDate::from_ymd_opt(2019, 1, 1).unwrap(),
+                time: NaiveTime::from_hms_opt(12, 0, 0).unwrap(),
+            },
+            hash_algorithm: DEG_HashAlgorithm {
+                use_of_hash_algorithm: UseOfHashAlgorithm::OHA,
+                hash_algorithm: HashAlgorithm::MutuallyAgreed,
+                hash_algorithm_param_identifier: HashAlgorithmParameterIdentifier::IVC,
+                param_value: None,
+            },
+            signature_algorithm: DEG_SignatureAlgorithm {
+                use_of_signature_algorithm: UseOfSignatureAlgorithm::OSG,
+                signature_algorithm: SignatureAlgorithm::RSA,
+                operation_mode: OperationMode::ISO_97961,
+            },
+            key_name: DEG_KeyName {
+                institute_identifier: DEG_InstituteIdentifier {
+                    country_code: "280".to_string(),
+                    bank_code: 12345678,
+                },
+                user_id: "test1".to_string(),
+                key_type: KeyType::S,
+                key_no: 0,
+                key_version: 0,
+            },
+            certificate: None,
+        };
+        assert_eq!(
+            to_string(&signature_head).unwrap(),
+            "HNSHK:2:4+PIN:1+999+1234567+1+1+1::0+1+1:20190101:120000+1:999:1+6:10:16+280:12345678:test1:S:0:0'"
+        );
+    }
+
+    #[test]
+    fn test_serialize_trailing_empty_elements() {
+        let message_head = Seg_HNHBK_MessageHead {
+            segment_head: Seg_HNHBK_MessageHead::new_segment_head(1),
+            message_size: 123,
+            hbci_version: 300,
+            dialog_id: "0".to_string(),
+            message_no: 1,
+            reference_msg: None,
+        };
+        assert_eq!(
+            to_string(&message_head).unwrap(),
+            "HNHBK:1:3+000000000123+300+0+1'"
+        );
+
+        let signature_end = Seg_HNSHA_SignatureEnd {
+            segment_head: Seg_HNSHA_SignatureEnd::new_segment_head(6),
+            security_reference: "1234567".to_string(),
+            validation_result: None,
+            user_defined_signature: Some(DEG_UserDefinedSignature {
+                PIN: "1234".to_string(),
+                TAN: None,
+            }),
+        };
+        assert_eq!(
+            to_string(&signature_end).unwrap(),
+            "HNSHA:6:2+1234567++1234'"
+        );
+
+        let tan_submission = Seg_HKTAN_TwoStepTanSubmission {
+            segment_head: Seg_HKTAN_TwoStepTanSubmission::new_segment_head(5),
+            tan_process: TanProcess::Available,
+            segment_identifier: None,
+            account_international_issuer: None,
+            job_hash_value: None,
+            job_reference: None,
+            further_tan_follows: None,
+        };
+        assert_eq!(to_string(&tan_submission).unwrap(), "HKTAN:5:6+3'");
+    }
+
+    #[test]
+    fn test_binary_roundtrip_in_segment() {
+        let signature_end = Seg_HNSHA_SignatureEnd {
+            segment_head: Seg_HNSHA_SignatureEnd::new_segment_head(6),
+            security_reference: "1234567".to_string(),
+            validation_result: Some(vec![0, b'\'', b'@', 255]),
+            user_defined_signature: Some(DEG_UserDefinedSignature {
+                PIN: "1234".to_string(),
+                TAN: None,
+            }),
+        };
+        let serialized = to_bytes(&signature_end).unwrap();
+        let binary = b"+@4@\x00'@\xff+";
+        assert!(serialized.windows(binary.len()).any(|w| w == binary));
+
+        let parsed: Seg_HNSHA_SignatureEnd = from_bytes(&serialized).unwrap();
+        assert_eq!(parsed.validation_result, Some(vec![0, b'\'', b'@', 255]));
+        assert_eq!(parsed.user_defined_signature.unwrap().PIN, "1234");
+    }
+
+    #[test]
+    fn test_serialize_repeated_elements() {
+        let institute = |bank_code| DEG_InstituteIdentifier {
+            country_code: "280".to_string(),
+            bank_code,
+        };
+        let repeated = Seg_HIXYZ_Repeated {
+            segment_head: Seg_HIXYZ_Repeated::new_segment_head(4),
+            versions: vec![220, 300],
+            languages: DEG_Languages {
+                languages: vec![1, 2],
+            },
+            institutes: vec![institute(12345678), institute(87654321)],
+        };
+        let serialized = to_bytes(&repeated).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&serialized).unwrap(),
+            "HIXYZ:4:1+220+300++1:2+280:12345678+280:87654321'"
+        );
+
+        let parsed: Seg_HIXYZ_Repeated = from_bytes(&serialized).unwrap();
+        assert_eq!(parsed.versions, vec![220, 300]);
+        assert_eq!(parsed.languages.languages, vec![1, 2]);
+        assert_eq!(parsed.institutes.len(), 2);
+        assert_eq!(parsed.institutes[1].bank_code, 87654321);
+
+        let empty = Seg_HIXYZ_Repeated {
+            segment_head: Seg_HIXYZ_Repeated::new_segment_head(4),
+            versions: vec![],
+            languages: DEG_Languages { languages: vec![] },
+            institutes: vec![],
+        };
+        assert_eq!(to_string(&empty).unwrap(), "HIXYZ:4:1'");
+    }
+
+    #[test]
+    fn test_serialize_too_many_repetitions() {
+        let repeated = Seg_HIXYZ_Repeated {
+            segment_head: Seg_HIXYZ_Repeated::new_segment_head(4),
+            versions: vec![1, 2, 3, 4],
+            languages: DEG_Languages { languages: vec![] },
+            institutes: vec![],
+        };
+        assert!(to_bytes(&repeated).is_err());
+    }
+
+    #[derive(Debug, DataElementGroup)]
+    struct Inner {
+        first: Option<String>,
+        second: Option<String>,
+    }
+
+    #[derive(Debug, DataElementGroup)]
+    struct Outer {
+        #[fints(deg)]
+        inner: Inner,
+        last: String,
+    }
+
+    #[derive(Debug, Segment)]
+    #[fints(id = "HIXYZ", version = 1)]
+    struct Nested {
+        segment_head: DEG_SegmentHead,
+        outer: Outer,
+        trailing: Option<Outer>,
+    }
+
+    #[test]
+    fn test_nested_deg_width() {
+        use crate::data_types::DataElementGroup as _;
+        assert_eq!(Inner::WIDTH, 2);
+        assert_eq!(Outer::WIDTH, 3);
+    }
+
+    #[test]
+    fn test_serialize_nested_degs() {
+        let nested = Nested {
+            segment_head: Nested::new_segment_head(1),
+            outer: Outer {
+                inner: Inner {
+                    first: Some("a".to_string()),
+                    second: None,
+                },
+                last: "b".to_string(),
+            },
+            trailing: None,
+        };
+        let serialized = to_bytes(&nested).unwrap();
+        assert_eq!(std::str::from_utf8(&serialized).unwrap(), "HIXYZ:1:1+a::b'");
+
+        let parsed: Nested = from_bytes(&serialized).unwrap();
+        assert_eq!(parsed.outer.inner.first.as_deref(), Some("a"));
+        assert!(parsed.outer.inner.second.is_none());
+        assert_eq!(parsed.outer.last, "b");
+        assert!(parsed.trailing.is_none());
+    }
+
+    #[test]
+    fn test_serialize_deg() {
+        let institute = DEG_InstituteIdentifier {
+            country_code: "280".to_string(),
+            bank_code: 12345678,
+        };
+        assert_eq!(to_string(&institute).unwrap(), "280:12345678");
+    }
+
+    #[test]
+    fn test_serialize_encoding() {
+        let institute = |country_code: &str| DEG_InstituteIdentifier {
+            country_code: country_code.to_string(),
+            bank_code: 12345678,
+        };
+        assert_eq!(
+            to_bytes(&institute("€ä")).unwrap(),
+            b"\xa4\xe4:12345678".to_vec()
+        );
+        assert_eq!(
+            to_bytes(&institute("Łódź")),
+            Err(Error("'Ł' can't be encoded in ISO 8859-15".to_string()))
+        );
+        assert!(to_bytes(&institute("🙂")).is_err());
+    }
+
+    #[test]
+    fn test_serialize_unsupported() {
+        assert_eq!(
+            to_bytes(&1.5f64),
+            Err(Error("Serializing f64 is not supported".to_string()))
+        );
+        assert!(to_bytes(&()).is_err());
+        let map: std::collections::BTreeMap<u8, u8> = vec![(1, 2)].into_iter().collect();
+        assert_eq!(
+            to_bytes(&map),
+            Err(Error("Serializing maps is not supported".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_serialize_unknown_struct() {
+        #[derive(Debug, serde_derive::Serialize)]
+        struct Unknown {
+            value: String,
+        }
+
+        let unknown = Unknown {
+            value: "a".to_string(),
+        };
+        assert!(to_bytes(&unknown).is_err());
     }
 }
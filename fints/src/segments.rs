@@ -1,9 +1,39 @@
-use serde_derive::{Deserialize, Serialize};
+use chrono::NaiveDate;
+use fints_derive::Segment;
 
 use crate::data_types::*;
 
+/// A segment with a fixed identifier and version.
+///
+/// This is implemented by `#[derive(Segment)]` using the `#[fints(id = "...", version = N)]`
+/// attribute.
+pub trait Segment {
+    /// Segment identifier, e.g. `HKIDN`.
+    const ID: &'static str;
+
+    /// Segment version.
+    const VERSION: u16;
+
+    fn segment_head(&self) -> &DEG_SegmentHead;
+
+    fn segment_head_mut(&mut self) -> &mut DEG_SegmentHead;
+
+    /// Create a segment head for this segment at position `segment_no` in its message.
+    fn new_segment_head(segment_no: u16) -> DEG_SegmentHead
+    where
+        Self: Sized,
+    {
+        DEG_SegmentHead {
+            identifier: Self::ID.to_string(),
+            segment_no,
+            version: Self::VERSION,
+            reference_seg: None,
+        }
+    }
+}
+
 mod pad_to_12 {
-    use serde::{Deserializer, Serializer, Deserialize};
+    use serde::{Deserialize, Deserializer, Serializer};
 
     pub fn serialize<S>(number: &u64, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -23,7 +53,8 @@ mod pad_to_12 {
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Segment)]
+#[fints(id = "HNHBK", version = 3)]
 pub struct Seg_HNHBK_MessageHead {
     // Segmentkopf
     pub segment_head: DEG_SegmentHead,
@@ -47,7 +78,8 @@ pub struct Seg_HNHBK_MessageHead {
 
 /// B.5.1 Signaturkopf
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Segment)]
+#[fints(id = "HNSHK", version = 4)]
 pub struct Seg_HNSHK_SignatureHead {
     // Segmentkopf
     pub segment_head: DEG_SegmentHead,
@@ -89,8 +121,60 @@ pub struct Seg_HNSHK_SignatureHead {
     pub certificate: Option<DEG_Certificate>,
 }
 
+/// B.5.3 Verschlüsselungskopf
+///
+/// For PIN/TAN the encryption is a dummy but the envelope is mandatory nonetheless.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HNVSK", version = 3)]
+pub struct Seg_HNVSK_EncryptionHead {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Sicherheitsprofil
+    pub security_profile: DEG_SecurityProfile,
+
+    // Sicherheitsfunktion, kodiert
+    pub security_function: SecurityFunction,
+
+    // Rolle des Sicherheitslieferanten, kodiert
+    pub security_role: SecurityRole,
+
+    // Sicherheitsidentifikation, Details
+    pub security_identification_details: DEG_SecurityIdentificationDetails,
+
+    // Sicherheitsdatum und -uhrzeit
+    pub security_date: DEG_SecurityDate,
+
+    // Verschlüsselungsalgorithmus
+    pub encryption_algorithm: DEG_EncryptionAlgorithm,
+
+    // Schlüsselname
+    pub key_name: DEG_KeyName,
+
+    // Komprimierungsfunktion
+    pub compression_function: CompressionFunction,
+
+    // Zertifikat
+    pub certificate: Option<DEG_Certificate>,
+}
+
+/// B.5.4 Verschlüsselte Daten
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Segment)]
+#[fints(id = "HNVSD", version = 1)]
+pub struct Seg_HNVSD_EncryptedData {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Verschlüsselte Daten
+    #[serde(with = "serde_bytes")]
+    pub encrypted_data: Vec<u8>,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HNHBS", version = 1)]
 pub struct Seg_HNHBS_MessageEnd {
     // Segmentkopf
     pub segment_head: DEG_SegmentHead,
@@ -101,7 +185,8 @@ pub struct Seg_HNHBS_MessageEnd {
 
 // C.3.1.2 Segment: Identifikation
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Segment)]
+#[fints(id = "HKIDN", version = 2)]
 pub struct Seg_HKIDN_Identification {
     // Segmentkopf
     pub segment_head: DEG_SegmentHead,
@@ -121,7 +206,8 @@ pub struct Seg_HKIDN_Identification {
 
 // C.3.1.3 Segment: Verarbeitungsvorbereitung
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Segment)]
+#[fints(id = "HKVVB", version = 3)]
 pub struct Seg_HKVVB_ProcessingPreparation {
     // Segmentkopf
     pub segment_head: DEG_SegmentHead,
@@ -143,7 +229,8 @@ pub struct Seg_HKVVB_ProcessingPreparation {
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Segment)]
+#[fints(id = "HKTAN", version = 6)]
 pub struct Seg_HKTAN_TwoStepTanSubmission {
     // Segmentkopf
     pub segment_head: DEG_SegmentHead,
@@ -158,15 +245,20 @@ pub struct Seg_HKTAN_TwoStepTanSubmission {
     pub account_international_issuer: Option<DEG_AccountInternationalIssuer>,
 
     // Auftrags-Hashwert
+    #[serde(with = "serde_bytes")]
     pub job_hash_value: Option<Vec<u8>>,
 
     // Auftragsreferenz
     pub job_reference: Option<String>,
+
+    // Weitere TAN folgt
+    pub further_tan_follows: Option<bool>,
 }
 
 // C.3.1.4 Segment: Anforderung eines öffentlichen Schlüssels
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Segment)]
+#[fints(id = "HKISA", version = 2)]
 pub struct Seg_HKISA_RequestForPubkey {
     // Segmentkopf
     pub segment_head: DEG_SegmentHead,
@@ -189,7 +281,8 @@ pub struct Seg_HKISA_RequestForPubkey {
 
 // C.8.1.2 Segment: Synchronisierung
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Segment)]
+#[fints(id = "HKSYN", version = 3)]
 pub struct Seg_HKSYN_Synchronization {
     // Segmentkopf
     pub segment_head: DEG_SegmentHead,
@@ -198,9 +291,43 @@ pub struct Seg_HKSYN_Synchronization {
     pub synchronization_mode: SynchronizationMode,
 }
 
+/// C.8.1.3 Synchronisierungsantwort
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HISYN", version = 4)]
+pub struct Seg_HISYN_SynchronizationResponse {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Kundensystem-ID
+    pub customer_system_id: Option<String>,
+
+    // Nachrichtennummer
+    pub message_no: Option<u16>,
+
+    // Sicherheitsreferenznummer für Signierschlüssel
+    pub security_ref_no_signature_key: Option<u64>,
+
+    // Sicherheitsreferenznummer für Digitale Signatur
+    pub security_ref_no_digital_signature: Option<u64>,
+}
+
+// C.4.1.2 Segment: Dialogende
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HKEND", version = 1)]
+pub struct Seg_HKEND_DialogEnd {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Dialog-ID
+    pub dialog_id: String,
+}
+
 // B.5.2 Segment: Signaturabschluss
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Segment)]
+#[fints(id = "HNSHA", version = 2)]
 pub struct Seg_HNSHA_SignatureEnd {
     // Segmentkopf
     pub segment_head: DEG_SegmentHead,
@@ -209,8 +336,714 @@ pub struct Seg_HNSHA_SignatureEnd {
     pub security_reference: String,
 
     // Validierungsresultat
+    #[serde(with = "serde_bytes")]
     pub validation_result: Option<Vec<u8>>,
 
     // Benutzerdefinierte Signatur
     pub user_defined_signature: Option<DEG_UserDefinedSignature>,
 }
+
+/// B.7.2 Rückmeldungen zur Gesamtnachricht
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HIRMG", version = 2)]
+pub struct Seg_HIRMG_MessageFeedback {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Rückmeldung
+    #[fints(max = 99)]
+    pub feedback: Vec<DEG_Feedback>,
+}
+
+/// B.7.3 Rückmeldungen zu Segmenten
+///
+/// The segment this feedback refers to is given by `reference_seg` in the segment head.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HIRMS", version = 2)]
+pub struct Seg_HIRMS_SegmentFeedback {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Rückmeldung
+    #[fints(max = 99)]
+    pub feedback: Vec<DEG_Feedback>,
+}
+
+/// D.2 Bankparameter allgemein
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HIBPA", version = 3)]
+pub struct Seg_HIBPA_BankParameters {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // BPD-Version
+    pub bpd_version: u16,
+
+    // Kreditinstitutskennung
+    pub institute_identifier: DEG_InstituteIdentifier,
+
+    // Kreditinstitutsbezeichnung
+    pub bank_name: String,
+
+    // Anzahl Geschäftsvorfallarten pro Nachricht
+    pub max_jobs_per_message: u16,
+
+    // Unterstützte Sprachen
+    pub supported_languages: DEG_SupportedLanguages,
+
+    // Unterstützte HBCI-Versionen
+    pub supported_hbci_versions: DEG_SupportedHbciVersions,
+
+    // Maximale Nachrichtengröße
+    pub max_message_size: Option<u32>,
+
+    // Minimaler Timeout-Wert
+    pub min_timeout: Option<u32>,
+
+    // Maximaler Timeout-Wert
+    pub max_timeout: Option<u32>,
+}
+
+/// D.3 Kommunikationszugang rückmelden
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HIKOM", version = 4)]
+pub struct Seg_HIKOM_CommunicationAccess {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Kreditinstitutskennung
+    pub institute_identifier: DEG_InstituteIdentifier,
+
+    // Standardsprache
+    pub default_lang: DialogLang,
+
+    // Kommunikationsparameter
+    #[fints(max = 9)]
+    pub communication_parameters: Vec<DEG_CommunicationParameters>,
+}
+
+/// D.4 Sicherheitsverfahren
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HISHV", version = 3)]
+pub struct Seg_HISHV_SecurityMethods {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Mischung zulässig
+    pub mixing_allowed: bool,
+
+    // Unterstützte Sicherheitsverfahren
+    #[fints(max = 9)]
+    pub security_methods: Vec<DEG_SecurityMethods>,
+}
+
+/// PIN/TAN-spezifische Informationen
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HIPINS", version = 1)]
+pub struct Seg_HIPINS_PinTanInformation {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Maximale Anzahl Aufträge
+    pub max_jobs: u16,
+
+    // Anzahl Signaturen mindestens
+    pub min_signatures: u16,
+
+    // Sicherheitsklasse
+    pub security_class: u16,
+
+    // Parameter PIN/TAN-spezifische Informationen
+    pub pin_tan_information: DEG_PinTanInformation,
+}
+
+/// C.3.2.1 Userparameter allgemein
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HIUPA", version = 4)]
+pub struct Seg_HIUPA_UserParameters {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Benutzerkennung
+    pub user_id: String,
+
+    // UPD-Version
+    pub upd_version: u16,
+
+    // UPD-Verwendung
+    pub upd_usage: UpdUsage,
+
+    // Benutzername
+    pub username: Option<String>,
+
+    // Erweiterung, allgemein
+    pub extension: Option<String>,
+}
+
+/// C.3.2.2 Kontoinformation
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HIUPD", version = 6)]
+pub struct Seg_HIUPD_AccountInformation {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Kontoverbindung
+    pub account_connection: Option<DEG_AccountConnection>,
+
+    // IBAN
+    pub iban: Option<String>,
+
+    // Kunden-ID
+    pub customer_id: String,
+
+    // Kontoart
+    pub account_type: Option<u16>,
+
+    // Kontowährung
+    pub currency: Option<String>,
+
+    // Name des Kontoinhabers 1
+    pub holder_name_1: String,
+
+    // Name des Kontoinhabers 2
+    pub holder_name_2: Option<String>,
+
+    // Kontoproduktbezeichnung
+    pub product_name: Option<String>,
+
+    // Kontolimit
+    pub limit: Option<DEG_AccountLimit>,
+
+    // Erlaubte Geschäftsvorfälle
+    #[fints(max = 999)]
+    pub allowed_jobs: Vec<DEG_AllowedJob>,
+
+    // Erweiterung, kontobezogen
+    pub extension: Option<String>,
+}
+
+/// C.10.1.3 SEPA-Kontoverbindung anfordern, version 1
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HKSPA", version = 1)]
+pub struct Seg_HKSPA_SepaAccountInfo1 {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Kontoverbindung, all accounts if empty
+    #[fints(max = 999)]
+    pub accounts: Vec<DEG_AccountConnection>,
+}
+
+/// C.10.1.3 SEPA-Kontoverbindung anfordern, version 2
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HKSPA", version = 2)]
+pub struct Seg_HKSPA_SepaAccountInfo2 {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Kontoverbindung ZV, all accounts if empty
+    #[fints(max = 999)]
+    pub accounts: Vec<DEG_SepaAccountConnection>,
+}
+
+/// C.10.1.3 SEPA-Kontoverbindung anfordern, version 3
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HKSPA", version = 3)]
+pub struct Seg_HKSPA_SepaAccountInfo3 {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Kontoverbindung ZV, all accounts if empty
+    #[fints(max = 999)]
+    pub accounts: Vec<DEG_SepaAccountConnection>,
+}
+
+/// C.10.1.3 SEPA-Kontoverbindung rückmelden, version 1
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HISPA", version = 1)]
+pub struct Seg_HISPA_SepaAccountInfo1 {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Kontoverbindung ZV
+    #[fints(max = 999)]
+    pub accounts: Vec<DEG_SepaAccountConnection>,
+}
+
+/// C.10.1.3 SEPA-Kontoverbindung rückmelden, version 2
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HISPA", version = 2)]
+pub struct Seg_HISPA_SepaAccountInfo2 {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Kontoverbindung ZV
+    #[fints(max = 999)]
+    pub accounts: Vec<DEG_SepaAccountConnection>,
+}
+
+/// C.10.1.3 SEPA-Kontoverbindung rückmelden, version 3
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HISPA", version = 3)]
+pub struct Seg_HISPA_SepaAccountInfo3 {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Kontoverbindung ZV
+    #[fints(max = 999)]
+    pub accounts: Vec<DEG_SepaAccountConnection>,
+}
+
+/// C.2.1.2 Saldenabfrage, version 6
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HKSAL", version = 6)]
+pub struct Seg_HKSAL_Balance6 {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Kontoverbindung Auftraggeber
+    pub account: DEG_AccountConnection,
+
+    // Alle Konten
+    pub all_accounts: bool,
+
+    // Maximale Anzahl Einträge
+    pub max_entries: Option<u16>,
+
+    // Aufsetzpunkt
+    pub touchdown: Option<String>,
+}
+
+/// C.2.1.2 Saldenabfrage, version 7
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HKSAL", version = 7)]
+pub struct Seg_HKSAL_Balance7 {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Kontoverbindung international
+    pub account: DEG_InternationalAccountConnection,
+
+    // Alle Konten
+    pub all_accounts: bool,
+
+    // Maximale Anzahl Einträge
+    pub max_entries: Option<u16>,
+
+    // Aufsetzpunkt
+    pub touchdown: Option<String>,
+}
+
+/// C.2.1.2 Saldenrückmeldung, version 6
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HISAL", version = 6)]
+pub struct Seg_HISAL_Balance6 {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Kontoverbindung Auftraggeber
+    pub account: DEG_AccountConnection,
+
+    // Kontoproduktbezeichnung
+    pub product_name: String,
+
+    // Kontowährung
+    pub currency: String,
+
+    // Gebuchter Saldo
+    pub booked: DEG_Balance,
+
+    // Saldo der vorgemerkten Umsätze
+    pub pending: Option<DEG_Balance>,
+
+    // Kreditlinie
+    pub credit_line: Option<DEG_Amount>,
+
+    // Verfügbarer Betrag
+    pub available: Option<DEG_Amount>,
+
+    // Bereits verfügter Betrag
+    pub used: Option<DEG_Amount>,
+
+    // Überziehung
+    pub overdraft: Option<DEG_Amount>,
+
+    // Buchungszeitpunkt
+    pub booking_timestamp: Option<DEG_Timestamp>,
+
+    // Fälligkeit
+    #[serde(with = "fints_optional_date_format")]
+    pub due_date: Option<NaiveDate>,
+}
+
+/// C.2.1.2 Saldenrückmeldung, version 7
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HISAL", version = 7)]
+pub struct Seg_HISAL_Balance7 {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Kontoverbindung international
+    pub account: DEG_InternationalAccountConnection,
+
+    // Kontoproduktbezeichnung
+    pub product_name: String,
+
+    // Kontowährung
+    pub currency: String,
+
+    // Gebuchter Saldo
+    pub booked: DEG_Balance,
+
+    // Saldo der vorgemerkten Umsätze
+    pub pending: Option<DEG_Balance>,
+
+    // Kreditlinie
+    pub credit_line: Option<DEG_Amount>,
+
+    // Verfügbarer Betrag
+    pub available: Option<DEG_Amount>,
+
+    // Bereits verfügter Betrag
+    pub used: Option<DEG_Amount>,
+
+    // Überziehung
+    pub overdraft: Option<DEG_Amount>,
+
+    // Buchungszeitpunkt
+    pub booking_timestamp: Option<DEG_Timestamp>,
+
+    // Fälligkeit
+    #[serde(with = "fints_optional_date_format")]
+    pub due_date: Option<NaiveDate>,
+}
+
+/// C.2.1.1.1.1 Kontoumsätze anfordern/Zeitraum, version 6
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HKKAZ", version = 6)]
+pub struct Seg_HKKAZ_Statement6 {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Kontoverbindung Auftraggeber
+    pub account: DEG_AccountConnection,
+
+    // Alle Konten
+    pub all_accounts: bool,
+
+    // Von Datum
+    #[serde(with = "fints_optional_date_format")]
+    pub from_date: Option<NaiveDate>,
+
+    // Bis Datum
+    #[serde(with = "fints_optional_date_format")]
+    pub to_date: Option<NaiveDate>,
+
+    // Maximale Anzahl Einträge
+    pub max_entries: Option<u16>,
+
+    // Aufsetzpunkt
+    pub touchdown: Option<String>,
+}
+
+/// C.2.1.1.1.1 Kontoumsätze anfordern/Zeitraum, version 7
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HKKAZ", version = 7)]
+pub struct Seg_HKKAZ_Statement7 {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Kontoverbindung international
+    pub account: DEG_InternationalAccountConnection,
+
+    // Alle Konten
+    pub all_accounts: bool,
+
+    // Von Datum
+    #[serde(with = "fints_optional_date_format")]
+    pub from_date: Option<NaiveDate>,
+
+    // Bis Datum
+    #[serde(with = "fints_optional_date_format")]
+    pub to_date: Option<NaiveDate>,
+
+    // Maximale Anzahl Einträge
+    pub max_entries: Option<u16>,
+
+    // Aufsetzpunkt
+    pub touchdown: Option<String>,
+}
+
+/// C.2.1.1.1.1 Kontoumsätze rückmelden/Zeitraum
+///
+/// All versions share this layout.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HIKAZ", version = 7)]
+pub struct Seg_HIKAZ_Statement {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Gebuchte Umsätze (MT940)
+    #[serde(with = "serde_bytes")]
+    pub booked: Vec<u8>,
+
+    // Nicht gebuchte Umsätze (MT942)
+    #[serde(with = "serde_bytes")]
+    pub pending: Option<Vec<u8>>,
+}
+
+/// C.2.3.1.1.1 Kontoumsätze anfordern/Zeitraum (camt)
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HKCAZ", version = 1)]
+pub struct Seg_HKCAZ_CamtStatement {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Kontoverbindung international
+    pub account: DEG_InternationalAccountConnection,
+
+    // Unterstützte camt-Messages
+    pub supported_camt_messages: DEG_SupportedCamtMessages,
+
+    // Alle Konten
+    pub all_accounts: bool,
+
+    // Von Datum
+    #[serde(with = "fints_optional_date_format")]
+    pub from_date: Option<NaiveDate>,
+
+    // Bis Datum
+    #[serde(with = "fints_optional_date_format")]
+    pub to_date: Option<NaiveDate>,
+
+    // Maximale Anzahl Einträge
+    pub max_entries: Option<u16>,
+
+    // Aufsetzpunkt
+    pub touchdown: Option<String>,
+}
+
+/// C.2.3.1.1.1 Kontoumsätze rückmelden/Zeitraum (camt)
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HICAZ", version = 1)]
+pub struct Seg_HICAZ_CamtStatement {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Kontoverbindung international
+    pub account: DEG_InternationalAccountConnection,
+
+    // camt-Deskriptor
+    pub camt_descriptor: String,
+
+    // Gebuchte Umsätze
+    pub booked: DEG_BookedCamtStatements,
+
+    // Nicht gebuchte Umsätze
+    #[serde(with = "serde_bytes")]
+    pub pending: Option<Vec<u8>>,
+}
+
+/// C.2.3.1.1.1 Kontoumsätze/Zeitraum (camt) Parameter
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HICAZS", version = 1)]
+pub struct Seg_HICAZS_CamtStatementParameters {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Maximale Anzahl Aufträge
+    pub max_jobs: u16,
+
+    // Anzahl Signaturen mindestens
+    pub min_signatures: u16,
+
+    // Sicherheitsklasse
+    pub security_class: u16,
+
+    // Parameter Kontoumsätze/Zeitraum camt
+    pub parameters: DEG_CamtStatementParameters,
+}
+
+/// C.2.1.2.6 Zwei-Schritt-TAN-Einreichung, Rückmeldung
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HITAN", version = 6)]
+pub struct Seg_HITAN_TwoStepTanResponse {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // TAN-Prozess
+    pub tan_process: TanProcess,
+
+    // Auftrags-Hashwert
+    #[serde(with = "serde_bytes")]
+    pub job_hash_value: Option<Vec<u8>>,
+
+    // Auftragsreferenz
+    pub job_reference: Option<String>,
+
+    // Challenge
+    pub challenge: Option<String>,
+
+    // Challenge HHD_UC
+    #[serde(with = "serde_bytes")]
+    pub challenge_hhd_uc: Option<Vec<u8>>,
+
+    // Gültigkeitsdatum und -uhrzeit für Challenge
+    pub challenge_valid_until: Option<DEG_Timestamp>,
+
+    // Bezeichnung des TAN-Mediums
+    pub tan_medium_name: Option<String>,
+}
+
+/// C.10.1.3 SEPA-Kontoverbindung anfordern, Parameter
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HISPAS", version = 1)]
+pub struct Seg_HISPAS_SepaAccountParameters1 {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Maximale Anzahl Aufträge
+    pub max_jobs: u16,
+
+    // Anzahl Signaturen mindestens
+    pub min_signatures: u16,
+
+    // Sicherheitsklasse
+    pub security_class: u16,
+
+    // Parameter SEPA-Kontoverbindung anfordern
+    pub parameters: DEG_SepaAccountParameters1,
+}
+
+/// C.10.1.3 SEPA-Kontoverbindung anfordern, Parameter
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HISPAS", version = 2)]
+pub struct Seg_HISPAS_SepaAccountParameters2 {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Maximale Anzahl Aufträge
+    pub max_jobs: u16,
+
+    // Anzahl Signaturen mindestens
+    pub min_signatures: u16,
+
+    // Sicherheitsklasse
+    pub security_class: u16,
+
+    // Parameter SEPA-Kontoverbindung anfordern
+    pub parameters: DEG_SepaAccountParameters2,
+}
+
+/// C.10.1.3 SEPA-Kontoverbindung anfordern, Parameter
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HISPAS", version = 3)]
+pub struct Seg_HISPAS_SepaAccountParameters3 {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Maximale Anzahl Aufträge
+    pub max_jobs: u16,
+
+    // Anzahl Signaturen mindestens
+    pub min_signatures: u16,
+
+    // Sicherheitsklasse
+    pub security_class: u16,
+
+    // Parameter SEPA-Kontoverbindung anfordern
+    pub parameters: DEG_SepaAccountParameters2,
+}
+
+/// C.10.2.1.1 SEPA-Einzelüberweisung
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HKCCS", version = 1)]
+pub struct Seg_HKCCS_SepaTransfer {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Kontoverbindung international
+    pub account: DEG_InternationalAccountConnection,
+
+    // SEPA Descriptor
+    pub sepa_descriptor: String,
+
+    // SEPA pain message
+    #[serde(with = "serde_bytes")]
+    pub sepa_pain_message: Vec<u8>,
+}
+
+/// C.10.3.1.1 SEPA-Sammelüberweisung
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HKCCM", version = 1)]
+pub struct Seg_HKCCM_SepaBatchTransfer {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Kontoverbindung international
+    pub account: DEG_InternationalAccountConnection,
+
+    // Summenfeld
+    pub sum_amount: DEG_Amount,
+
+    // Einzelbuchung gewünscht
+    pub single_booking: Option<bool>,
+
+    // SEPA Descriptor
+    pub sepa_descriptor: String,
+
+    // SEPA pain message
+    #[serde(with = "serde_bytes")]
+    pub sepa_pain_message: Vec<u8>,
+}
+
+/// C.10.3.1.1 SEPA-Sammelüberweisung, Parameter
+#[allow(non_camel_case_types)]
+#[derive(Debug, Segment)]
+#[fints(id = "HICCMS", version = 1)]
+pub struct Seg_HICCMS_SepaBatchTransferParameters {
+    // Segmentkopf
+    pub segment_head: DEG_SegmentHead,
+
+    // Maximale Anzahl Aufträge
+    pub max_jobs: u16,
+
+    // Anzahl Signaturen mindestens
+    pub min_signatures: u16,
+
+    // Sicherheitsklasse
+    pub security_class: u16,
+
+    // Parameter SEPA-Sammelüberweisung
+    pub parameters: DEG_BatchTransferParameters,
+}
@@ -0,0 +1,179 @@
+use crate::data_types::SecurityFunction;
+use crate::de::{RawMessage, RawSegment};
+use serde_derive::{Deserialize, Serialize};
+
+/// Bank or user parameter data (BPD or UPD) as received from the bank.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ParameterData {
+    /// Version as announced by the bank or `0` if we don't have any parameter data yet.
+    pub version: u16,
+
+    /// The segments making up the parameter data.
+    pub segments: Vec<RawSegment>,
+}
+
+impl ParameterData {
+    /// Extract the parameter data from a response. `head` is the segment announcing the version
+    /// in the DE at `version_index`.
+    fn extract<F>(
+        response: &RawMessage,
+        head: &str,
+        version_index: usize,
+        is_part: F,
+    ) -> Option<ParameterData>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let version = response.find(head)?.element(version_index)?.parse().ok()?;
+        let segments = response
+            .segments
+            .iter()
+            .filter(|segment| is_part(segment.identifier()))
+            .cloned()
+            .collect();
+        Some(ParameterData { version, segments })
+    }
+}
+
+/// Segments belonging to the BPD. Besides the general ones these are the job parameters such as
+/// HISALS, which are named after the job with an `S` appended.
+fn is_bpd_segment(identifier: &str) -> bool {
+    match identifier {
+        "HIBPA" | "HIKOM" | "HISHV" | "HIKPV" => true,
+        _ => identifier.len() == 6 && identifier.starts_with("HI") && identifier.ends_with('S'),
+    }
+}
+
+fn is_upd_segment(identifier: &str) -> bool {
+    identifier == "HIUPA" || identifier == "HIUPD"
+}
+
+/// What the bank told us about itself and the user which should be kept between runs.
+///
+/// Without this, every run needs a new synchronization and downloads the BPD and UPD again.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClientState {
+    /// The `customer_system_id` starts at `0` (as per C.8) and will be assigned by the bank on
+    /// first sync.
+    pub customer_system_id: String,
+
+    /// Bank parameter data.
+    pub bpd: ParameterData,
+
+    /// User parameter data.
+    pub upd: ParameterData,
+
+    /// The TAN method (security function) chosen by the user.
+    pub tan_method: Option<String>,
+}
+
+impl Default for ClientState {
+    fn default() -> Self {
+        ClientState {
+            customer_system_id: "0".to_string(),
+            bpd: ParameterData::default(),
+            upd: ParameterData::default(),
+            tan_method: None,
+        }
+    }
+}
+
+impl ClientState {
+    /// Whether the bank already assigned a customer system id.
+    pub fn is_synchronized(&self) -> bool {
+        self.customer_system_id != "0"
+    }
+
+    /// Security function messages are signed with: the chosen two-step TAN method or the
+    /// single-step method `999` if there is none.
+    pub fn security_function(&self) -> SecurityFunction {
+        self.tan_method
+            .as_deref()
+            .and_then(|method| method.parse::<u16>().ok())
+            .map(SecurityFunction::from)
+            .unwrap_or(SecurityFunction::SingleStepAuth)
+    }
+
+    /// Choose a TAN method among the ones the bank allows for the user, keeping the current
+    /// choice if it's still allowed.
+    pub(crate) fn select_tan_method(&mut self, allowed: &[String]) {
+        if let Some(method) = &self.tan_method {
+            if allowed.contains(method) {
+                return;
+            }
+        }
+        self.tan_method = allowed.iter().find(|method| *method != "999").cloned();
+    }
+
+    /// Replace BPD and UPD by the ones contained in a response, if any.
+    ///
+    /// Banks only send them if the versions we announced are outdated.
+    pub(crate) fn update_parameter_data(&mut self, response: &RawMessage) {
+        if let Some(bpd) = ParameterData::extract(response, "HIBPA", 1, is_bpd_segment) {
+            self.bpd = bpd;
+        }
+        if let Some(upd) = ParameterData::extract(response, "HIUPA", 2, is_upd_segment) {
+            self.upd = upd;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_update_parameter_data() {
+        let response = RawMessage::from_bytes(
+            b"HNHBK:1:3+000000000200+300+abc+1+abc:1'\
+              HIRMG:2:2+0010::Nachricht entgegengenommen.'\
+              HIRMS:3:2:4+3920::Zugelassene Verfahren.:910'\
+              HIBPA:4:3:4+12+280:12345678+Testbank+3+1+300'\
+              HIKOM:5:4:4+280:12345678+1+3:https?://example.com'\
+              HISALS:6:7:4+1+1+1'\
+              HIUPA:7:4:4+test1+4+0'\
+              HIUPD:8:6:4+1234567::280:12345678+DE00123456780001234567+test1++EUR+Max'\
+              HNHBS:9:1+1'",
+        )
+        .unwrap();
+        let mut state = ClientState::default();
+        state.update_parameter_data(&response);
+
+        assert_eq!(state.bpd.version, 12);
+        let identifiers: Vec<_> = state.bpd.segments.iter().map(|s| s.identifier()).collect();
+        assert_eq!(identifiers, vec!["HIBPA", "HIKOM", "HISALS"]);
+        assert_eq!(state.upd.version, 4);
+        assert_eq!(state.upd.segments.len(), 2);
+
+        // Parameter data is kept if the bank doesn't send new one.
+        let response =
+            RawMessage::from_bytes(b"HIRMG:2:2+0010::Nachricht entgegengenommen.'").unwrap();
+        state.update_parameter_data(&response);
+        assert_eq!(state.bpd.version, 12);
+
+        let json = serde_json::to_string(&state).unwrap();
+        assert_eq!(serde_json::from_str::<ClientState>(&json).unwrap(), state);
+    }
+
+    #[test]
+    fn test_select_tan_method() {
+        let mut state = ClientState::default();
+        assert_eq!(state.security_function(), SecurityFunction::SingleStepAuth);
+
+        state.select_tan_method(&["999".to_string()]);
+        assert_eq!(state.tan_method, None);
+
+        let allowed = vec!["999".to_string(), "920".to_string(), "942".to_string()];
+        state.select_tan_method(&allowed);
+        assert_eq!(state.tan_method.as_deref(), Some("920"));
+        assert_eq!(
+            state.security_function(),
+            SecurityFunction::TwoStepAuth(920)
+        );
+
+        state.tan_method = Some("942".to_string());
+        state.select_tan_method(&allowed);
+        assert_eq!(state.tan_method.as_deref(), Some("942"));
+    }
+}
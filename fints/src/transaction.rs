@@ -0,0 +1,59 @@
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionStatus {
+    #[default]
+    Booked,
+
+    /// Not yet booked (vorgemerkt).
+    Pending,
+}
+
+/// A single transaction of an account statement, no matter whether it was received as MT940
+/// or camt.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Transaction {
+    pub status: TransactionStatus,
+
+    /// Booking date, missing for pending transactions.
+    pub booking_date: Option<NaiveDate>,
+
+    pub value_date: Option<NaiveDate>,
+
+    /// Amount, negative for debits.
+    pub amount: Decimal,
+
+    pub currency: String,
+
+    /// Business transaction code (Geschäftsvorfallcode), e.g. `166` for a transfer.
+    pub transaction_code: Option<String>,
+
+    /// Booking text, e.g. `SEPA-UEBERWEISUNG`.
+    pub booking_text: Option<String>,
+
+    pub primanota: Option<String>,
+
+    /// Remittance information. For SEPA transactions, this is only the actual purpose
+    /// (`SVWZ+`) without the references.
+    pub purpose: Option<String>,
+
+    pub counterparty_name: Option<String>,
+
+    /// IBAN or, for non-SEPA transactions, account number of the counterparty.
+    pub counterparty_iban: Option<String>,
+
+    /// BIC or, for non-SEPA transactions, bank code of the counterparty.
+    pub counterparty_bic: Option<String>,
+
+    pub end_to_end_reference: Option<String>,
+
+    pub mandate_reference: Option<String>,
+
+    pub creditor_id: Option<String>,
+
+    pub customer_reference: Option<String>,
+
+    pub bank_reference: Option<String>,
+}
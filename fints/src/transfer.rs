@@ -0,0 +1,28 @@
+use rust_decimal::Decimal;
+use serde_derive::{Deserialize, Serialize};
+
+/// The receiving side of a SEPA credit transfer.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Recipient {
+    pub name: String,
+
+    pub iban: String,
+
+    /// BIC of the recipient's bank, only needed for transfers outside the EEA.
+    pub bic: Option<String>,
+}
+
+/// A SEPA credit transfer in EUR.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transfer {
+    pub recipient: Recipient,
+
+    /// Amount in EUR, positive with at most two decimal places.
+    pub amount: Decimal,
+
+    /// Unstructured remittance information, at most 140 characters of the SEPA character set.
+    pub purpose: String,
+
+    /// End-to-end reference passed on to the recipient, `NOTPROVIDED` if missing.
+    pub end_to_end_reference: Option<String>,
+}
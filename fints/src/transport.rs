@@ -0,0 +1,26 @@
+use log::debug;
+use std::fmt::Debug;
+
+use crate::error::Error;
+
+/// Delivers serialized messages to the bank and returns its serialized responses.
+///
+/// [`PinTanClient`](crate::PinTanClient) uses [`HttpTransport`] unless told otherwise.
+pub trait Transport: Debug {
+    /// Send `message` to `url` and return the response. Failures to reach the bank are
+    /// reported as [`Error::Transport`].
+    fn send(&mut self, url: &str, message: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
+/// Sends messages base64 encoded in an HTTP POST request as the PIN/TAN specification asks.
+#[derive(Debug, Default)]
+pub struct HttpTransport;
+
+impl Transport for HttpTransport {
+    fn send(&mut self, url: &str, message: &[u8]) -> Result<Vec<u8>, Error> {
+        let client = reqwest::blocking::Client::new();
+        let response = client.post(url).body(base64::encode(message)).send()?;
+        debug!("Response status {}", response.status());
+        Ok(base64::decode(&response.text()?)?)
+    }
+}
@@ -1,15 +1,87 @@
+/// Characters with a special meaning in FinTS which have to be escaped inside of values.
+const SPECIAL_CHARACTERS: [char; 5] = ['?', '+', ':', '\'', '@'];
+
 /// Escape `s` to be FinTS compliant.
-fn escape_fints(s: &str) -> String {
-    s.replace("?", "??")
-        .replace("+", "?+")
-        .replace(":", "?:")
-        .replace("@", "?@")
+pub fn escape_fints(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if SPECIAL_CHARACTERS.contains(&c) {
+            escaped.push('?');
+        }
+        escaped.push(c);
+    }
+    escaped
 }
 
 /// Unescape `s` from a FinTS-escaped format.
-fn unescape_fints(s: &str) -> String {
-    s.replace("??", "?")
-        .replace("?+", "+")
-        .replace("?:", ":")
-        .replace("?@", "@")
+pub fn unescape_fints(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '?' {
+            if let Some(escaped) = chars.next() {
+                unescaped.push(escaped);
+                continue;
+            }
+        }
+        unescaped.push(c);
+    }
+    unescaped
+}
+
+/// What a struct stands for on the wire.
+///
+/// The derives in `fints_derive` hand this to the serializer and deserializer as the name of the
+/// struct as serde has no other way of passing on type information.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StructKind {
+    Message,
+    Segment,
+    DataElementGroup,
+}
+
+impl StructKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            StructKind::Message => "$fints::Message",
+            StructKind::Segment => "$fints::Segment",
+            StructKind::DataElementGroup => "$fints::DataElementGroup",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<StructKind> {
+        [
+            StructKind::Message,
+            StructKind::Segment,
+            StructKind::DataElementGroup,
+        ]
+        .iter()
+        .copied()
+        .find(|kind| kind.name() == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_escape_fints() {
+        assert_eq!(escape_fints("plain text"), "plain text");
+        assert_eq!(escape_fints("a+b:c'd?e@f"), "a?+b?:c?'d??e?@f");
+    }
+
+    #[test]
+    fn test_unescape_fints() {
+        assert_eq!(unescape_fints("a?+b?:c?'d??e?@f"), "a+b:c'd?e@f");
+        assert_eq!(unescape_fints("???+"), "?+");
+    }
+
+    #[test]
+    fn test_escape_roundtrip() {
+        for s in &["", "?", "??+", "1234'+", "@@::"] {
+            assert_eq!(unescape_fints(&escape_fints(s)), *s);
+        }
+    }
 }
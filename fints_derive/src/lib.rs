@@ -1,40 +1,500 @@
 extern crate proc_macro;
 use crate::proc_macro::TokenStream;
 use quote::quote;
-use syn;
 
-#[proc_macro_derive(Message)]
+/// Derive `Message` as well as `Serialize` and `Deserialize` for a message.
+///
+/// All fields of a message have to be segments, optional segments or lists of segments. A
+/// segment that refers to another segment of the message can be annotated with
+/// `#[fints(reference = "field")]` so that its `reference_seg` is set when numbering segments.
+#[proc_macro_derive(Message, attributes(fints, serde))]
 pub fn message_macro_derive(input: TokenStream) -> TokenStream {
     // Construct a representation of Rust code as a syntax tree
     // that we can manipulate
     let ast = syn::parse(input).unwrap();
 
     // Build the trait implementation
-    impl_message_macro(&ast)
+    let mut gen = impl_message_macro(&ast);
+    gen.extend(impl_fints_serde(&ast, "Message"));
+    gen
+}
+
+/// Derive `Segment` as well as `Serialize` and `Deserialize` for a segment.
+///
+/// The segment's identifier and version have to be given using
+/// `#[fints(id = "HKIDN", version = 2)]` and the segment needs a `segment_head` field.
+///
+/// Fields can be annotated with `#[fints(max = N)]` to mark them as repeated DEs or DEGs with
+/// at most `N` repetitions and with `#[serde(with = "module")]` for custom formatting.
+#[proc_macro_derive(Segment, attributes(fints, serde))]
+pub fn segment_macro_derive(input: TokenStream) -> TokenStream {
+    let ast = syn::parse(input).unwrap();
+    let mut gen = impl_segment_macro(&ast).unwrap_or_else(|err| err.to_compile_error().into());
+    gen.extend(impl_fints_serde(&ast, "Segment"));
+    gen
+}
+
+/// Derive `DataElementGroup` as well as `Serialize` and `Deserialize` for a DEG.
+///
+/// Takes the same field attributes as `#[derive(Segment)]`. Fields holding DEGs themselves have
+/// to be marked with `#[fints(deg)]` so that their components are counted.
+#[proc_macro_derive(DataElementGroup, attributes(fints, serde))]
+pub fn data_element_group_macro_derive(input: TokenStream) -> TokenStream {
+    let ast = syn::parse(input).unwrap();
+    let mut gen = impl_data_element_group(&ast).unwrap_or_else(|err| err.to_compile_error().into());
+    gen.extend(impl_fints_serde(&ast, "DataElementGroup"));
+    gen
+}
+
+/// Find the field holding the message head (HNHBK) since that's where the message size goes.
+fn message_head_field(ast: &syn::DeriveInput) -> syn::Result<&syn::Ident> {
+    if let syn::Data::Struct(data) = &ast.data {
+        for field in &data.fields {
+            if let syn::Type::Path(path) = &field.ty {
+                let is_message_head = path
+                    .path
+                    .segments
+                    .last()
+                    .is_some_and(|segment| segment.ident == "Seg_HNHBK_MessageHead");
+                if let (true, Some(ident)) = (is_message_head, &field.ident) {
+                    return Ok(ident);
+                }
+            }
+        }
+    }
+    Err(syn::Error::new_spanned(
+        &ast.ident,
+        "A message needs a named field of type Seg_HNHBK_MessageHead",
+    ))
+}
+
+/// Return `T` if `ty` is `Option<T>`.
+fn option_inner(ty: &syn::Type) -> Option<&syn::Type> {
+    generic_inner(ty, "Option")
+}
+
+/// Return `T` if `ty` is `#wrapper<T>`, e.g. `Option<T>` or `Vec<T>`.
+fn generic_inner<'a>(ty: &'a syn::Type, wrapper: &str) -> Option<&'a syn::Type> {
+    let segment = match ty {
+        syn::Type::Path(path) => path.path.segments.last()?,
+        _ => return None,
+    };
+    if segment.ident != wrapper {
+        return None;
+    }
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) => match args.args.first()? {
+            syn::GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
 }
 
 fn impl_message_macro(ast: &syn::DeriveInput) -> TokenStream {
     let name = &ast.ident;
+    let message_head = match message_head_field(ast) {
+        Ok(ident) => ident,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let fields = match parse_fields(ast) {
+        Ok(fields) => fields,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let idents = fields.iter().map(|f| f.ident);
+    let references = fields.iter().filter_map(|field| {
+        let ident = field.ident;
+        field.reference.as_ref().map(|reference| {
+            quote! {
+                let reference_seg = ::fints::messages::Segments::first_segment_no(&self.#reference);
+                ::fints::messages::Segments::set_reference_seg(&mut self.#ident, reference_seg);
+            }
+        })
+    });
     let gen = quote! {
-        impl Message for #name {
+        impl ::fints::messages::Message for #name {
+            /// Number all present segments in order, starting at 1.
+            fn number_segments(&mut self) {
+                let mut segment_no = 0;
+                #(::fints::messages::Segments::number_segments(&mut self.#idents, &mut segment_no);)*
+                #(#references)*
+            }
+
             /// Take care of everything the message needs for sending:
             /// 1. serialize
-            /// 2. encode as base64
-            /// 3. take byte length of resulting encoded message
-            /// 4. write back byte length to original message
-            /// 5. serialize
-            /// 6. encode as base64
-            /// 7. return
-            fn prepare_message_for_sending(&self) -> String {
-                let serialized = to_string(&self).unwrap();
-                println!("{}", &serialized);
-                base64::encode(&serialized)
+            /// 2. take byte length of the serialized message
+            /// 3. write back byte length to original message
+            /// 4. serialize
+            /// 5. encode as base64
+            /// 6. return
+            ///
+            /// The message size is always serialized with the same width so writing it back
+            /// doesn't change the length of the message.
+            fn prepare_message_for_sending(&mut self) -> std::result::Result<String, ::fints::se::Error> {
+                ::fints::messages::Message::number_segments(self);
+                self.#message_head.message_size = 0;
+                let serialized = ::fints::se::to_bytes(&*self)?;
+                self.#message_head.message_size = serialized.len() as u64;
+                let serialized = ::fints::se::to_bytes(&*self)?;
+                debug_assert_eq!(serialized.len() as u64, self.#message_head.message_size);
+                Ok(::base64::encode(&serialized))
             }
         }
     };
     gen.into()
 }
 
+/// Parse `#[fints(id = "...", version = N)]` on a segment.
+fn segment_metadata(ast: &syn::DeriveInput) -> syn::Result<(syn::LitStr, syn::LitInt)> {
+    let mut id = None;
+    let mut version = None;
+
+    for attr in ast.attrs.iter().filter(|attr| attr.path.is_ident("fints")) {
+        let list = match attr.parse_meta()? {
+            syn::Meta::List(list) => list,
+            meta => {
+                return Err(syn::Error::new_spanned(
+                    meta,
+                    "Expected a list of attributes",
+                ))
+            }
+        };
+        for nested in &list.nested {
+            match nested {
+                syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                    path,
+                    lit: syn::Lit::Str(lit),
+                    ..
+                })) if path.is_ident("id") => id = Some(lit.clone()),
+                syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                    path,
+                    lit: syn::Lit::Int(lit),
+                    ..
+                })) if path.is_ident("version") => version = Some(lit.clone()),
+                _ => return Err(syn::Error::new_spanned(nested, "Unsupported attribute")),
+            }
+        }
+    }
+
+    match (id, version) {
+        (Some(id), Some(version)) => Ok((id, version)),
+        _ => Err(syn::Error::new_spanned(
+            &ast.ident,
+            "A segment needs #[fints(id = \"...\", version = N)]",
+        )),
+    }
+}
+
+fn impl_segment_macro(ast: &syn::DeriveInput) -> syn::Result<TokenStream> {
+    let name = &ast.ident;
+    let (id, version) = segment_metadata(ast)?;
+    let gen = quote! {
+        impl ::fints::segments::Segment for #name {
+            const ID: &'static str = #id;
+            const VERSION: u16 = #version;
+
+            fn segment_head(&self) -> &::fints::data_types::DEG_SegmentHead {
+                &self.segment_head
+            }
+
+            fn segment_head_mut(&mut self) -> &mut ::fints::data_types::DEG_SegmentHead {
+                &mut self.segment_head
+            }
+        }
+    };
+    Ok(gen.into())
+}
+
+/// A struct field together with the attributes relevant for FinTS.
+struct Field<'a> {
+    ident: &'a syn::Ident,
+    ty: &'a syn::Type,
+
+    /// Module with custom `serialize` and `deserialize` functions from `#[serde(with = "...")]`.
+    with: Option<syn::Path>,
+
+    /// Maximum number of repetitions from `#[fints(max = N)]`.
+    max: Option<syn::LitInt>,
+
+    /// The field of another segment in the same message this segment refers to from
+    /// `#[fints(reference = "field")]`.
+    reference: Option<syn::Ident>,
+
+    /// Whether the field holds a DEG from `#[fints(deg)]`.
+    deg: bool,
+}
+
+fn parse_field(field: &syn::Field) -> syn::Result<Field<'_>> {
+    let ident = field
+        .ident
+        .as_ref()
+        .ok_or_else(|| syn::Error::new_spanned(field, "Only named fields are supported"))?;
+    let mut with = None;
+    let mut max = None;
+    let mut reference = None;
+    let mut deg = false;
+
+    for attr in &field.attrs {
+        let is_serde = attr.path.is_ident("serde");
+        if !is_serde && !attr.path.is_ident("fints") {
+            continue;
+        }
+        let list = match attr.parse_meta()? {
+            syn::Meta::List(list) => list,
+            meta => {
+                return Err(syn::Error::new_spanned(
+                    meta,
+                    "Expected a list of attributes",
+                ))
+            }
+        };
+        for nested in &list.nested {
+            match nested {
+                syn::NestedMeta::Meta(syn::Meta::NameValue(nv))
+                    if is_serde && nv.path.is_ident("with") =>
+                {
+                    if let syn::Lit::Str(lit) = &nv.lit {
+                        with = Some(lit.parse()?);
+                        continue;
+                    }
+                }
+                syn::NestedMeta::Meta(syn::Meta::NameValue(nv))
+                    if !is_serde && nv.path.is_ident("max") =>
+                {
+                    if let syn::Lit::Int(lit) = &nv.lit {
+                        max = Some(lit.clone());
+                        continue;
+                    }
+                }
+                syn::NestedMeta::Meta(syn::Meta::NameValue(nv))
+                    if !is_serde && nv.path.is_ident("reference") =>
+                {
+                    if let syn::Lit::Str(lit) = &nv.lit {
+                        reference = Some(lit.parse()?);
+                        continue;
+                    }
+                }
+                syn::NestedMeta::Meta(syn::Meta::Path(path))
+                    if !is_serde && path.is_ident("deg") =>
+                {
+                    deg = true;
+                    continue;
+                }
+                _ => {}
+            }
+            return Err(syn::Error::new_spanned(nested, "Unsupported attribute"));
+        }
+    }
+
+    if with.is_some() && max.is_some() {
+        return Err(syn::Error::new_spanned(
+            ident,
+            "`with` and `max` can't be combined",
+        ));
+    }
+    if with.is_some() && deg {
+        return Err(syn::Error::new_spanned(
+            ident,
+            "`with` and `deg` can't be combined",
+        ));
+    }
+
+    Ok(Field {
+        ident,
+        ty: &field.ty,
+        with,
+        max,
+        reference,
+        deg,
+    })
+}
+
+fn parse_fields(ast: &syn::DeriveInput) -> syn::Result<Vec<Field<'_>>> {
+    match &ast.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) => fields.named.iter().map(parse_field).collect(),
+        _ => Err(syn::Error::new_spanned(
+            &ast.ident,
+            "Only structs with named fields are supported",
+        )),
+    }
+}
+
+fn impl_fints_serde(ast: &syn::DeriveInput, kind: &str) -> TokenStream {
+    try_impl_fints_serde(ast, kind).unwrap_or_else(|err| err.to_compile_error().into())
+}
+
+/// The number of components a field takes up inside a DEG.
+fn component_width(field: &Field) -> impl quote::ToTokens {
+    let width = |ty: &syn::Type| {
+        let ty = option_inner(ty).unwrap_or(ty);
+        if field.deg {
+            quote! { <#ty as ::fints::data_types::DataElementGroup>::WIDTH }
+        } else {
+            quote! { 1 }
+        }
+    };
+    if field.with.is_some() {
+        quote! { 1 }
+    } else if let Some(max) = &field.max {
+        let element = width(generic_inner(field.ty, "Vec").unwrap_or(field.ty));
+        quote! { #max * #element }
+    } else {
+        width(field.ty)
+    }
+}
+
+/// Implement `DataElementGroup` so that optional DEGs nested in other DEGs know how many
+/// components to check.
+fn impl_data_element_group(ast: &syn::DeriveInput) -> syn::Result<TokenStream> {
+    let name = &ast.ident;
+    let widths = parse_fields(ast)?
+        .iter()
+        .map(component_width)
+        .collect::<Vec<_>>();
+    let gen = quote! {
+        impl ::fints::data_types::DataElementGroup for #name {
+            const WIDTH: usize = 0 #(+ #widths)*;
+        }
+    };
+    Ok(gen.into())
+}
+
+/// Generate `Serialize` and `Deserialize` for messages, segments and DEGs.
+///
+/// We can't use serde's derives here as they have no way of passing our own field attributes
+/// on to the serializer. The `kind` (a variant of `StructKind`) is passed as the struct name so
+/// that the serializer knows what it's dealing with.
+fn try_impl_fints_serde(ast: &syn::DeriveInput, kind: &str) -> syn::Result<TokenStream> {
+    let name = &ast.ident;
+    let name_str = name.to_string();
+    let kind_str = kind;
+    let kind: syn::Path = syn::parse_str(&format!("::fints::utils::StructKind::{}", kind))?;
+    let fields = parse_fields(ast)?;
+    let len = fields.len();
+    let keys: Vec<String> = fields.iter().map(|f| f.ident.to_string()).collect();
+    let idents: Vec<&syn::Ident> = fields.iter().map(|f| f.ident).collect();
+
+    let serialize_fields = fields.iter().zip(&keys).map(|(field, key)| {
+        let ident = field.ident;
+        let ty = field.ty;
+        if let Some(with) = &field.with {
+            quote! {
+                {
+                    struct __SerializeWith<'__a>(&'__a #ty);
+                    impl serde::Serialize for __SerializeWith<'_> {
+                        fn serialize<__S>(&self, serializer: __S) -> std::result::Result<__S::Ok, __S::Error>
+                        where
+                            __S: serde::Serializer,
+                        {
+                            #with::serialize(self.0, serializer)
+                        }
+                    }
+                    state.serialize_field(#key, &__SerializeWith(&self.#ident))?;
+                }
+            }
+        } else if let Some(max) = &field.max {
+            quote! {
+                state.serialize_field(#key, &::fints::se::Repeated::new(&self.#ident, #max))?;
+            }
+        } else {
+            quote! {
+                state.serialize_field(#key, &self.#ident)?;
+            }
+        }
+    });
+
+    let deserialize_fields = fields.iter().enumerate().map(|(index, field)| {
+        let ident = field.ident;
+        let ty = field.ty;
+        let missing = quote! {
+            .ok_or_else(|| serde::de::Error::invalid_length(#index, &self))?
+        };
+        if let Some(with) = &field.with {
+            quote! {
+                let #ident: #ty = {
+                    struct __DeserializeWith(#ty);
+                    impl<'__de> serde::Deserialize<'__de> for __DeserializeWith {
+                        fn deserialize<__D>(deserializer: __D) -> std::result::Result<Self, __D::Error>
+                        where
+                            __D: serde::Deserializer<'__de>,
+                        {
+                            #with::deserialize(deserializer).map(__DeserializeWith)
+                        }
+                    }
+                    seq.next_element::<__DeserializeWith>()? #missing .0
+                };
+            }
+        } else if let Some(max) = &field.max {
+            quote! {
+                let #ident: #ty = seq.next_element_seed(::fints::de::Repeated::new(#max))? #missing;
+            }
+        } else if let (true, Some(inner)) = (kind_str == "Message", option_inner(ty)) {
+            // Optional segments are matched by identifier, not by position
+            quote! {
+                let #ident: #ty = seq.next_element_seed(::fints::de::OptionalSegment::<#inner>::new())? #missing;
+            }
+        } else if let (true, true, Some(inner)) =
+            (kind_str == "DataElementGroup", field.deg, option_inner(ty))
+        {
+            // Nested DEGs are only absent if all of their components are empty
+            quote! {
+                let #ident: #ty = seq.next_element_seed(::fints::de::OptionalDataElementGroup::<#inner>::new())? #missing;
+            }
+        } else {
+            quote! {
+                let #ident: #ty = seq.next_element()? #missing;
+            }
+        }
+    });
+
+    let gen = quote! {
+        impl serde::Serialize for #name {
+            fn serialize<__S>(&self, serializer: __S) -> std::result::Result<__S::Ok, __S::Error>
+            where
+                __S: serde::Serializer,
+            {
+                use serde::ser::SerializeStruct;
+                let mut state = serializer.serialize_struct(#kind.name(), #len)?;
+                #(#serialize_fields)*
+                state.end()
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for #name {
+            fn deserialize<__D>(deserializer: __D) -> std::result::Result<Self, __D::Error>
+            where
+                __D: serde::Deserializer<'de>,
+            {
+                struct __Visitor;
+
+                impl<'de> serde::de::Visitor<'de> for __Visitor {
+                    type Value = #name;
+
+                    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        formatter.write_str(concat!("struct ", #name_str))
+                    }
+
+                    #[allow(non_snake_case)]
+                    fn visit_seq<__A>(self, mut seq: __A) -> std::result::Result<#name, __A::Error>
+                    where
+                        __A: serde::de::SeqAccess<'de>,
+                    {
+                        #(#deserialize_fields)*
+                        Ok(#name { #(#idents),* })
+                    }
+                }
+
+                const FIELDS: &[&str] = &[#(#keys),*];
+                deserializer.deserialize_struct(#kind.name(), FIELDS, __Visitor)
+            }
+        }
+    };
+    Ok(gen.into())
+}
+
 #[cfg(test)]
 mod tests {
     #[test]